@@ -0,0 +1,456 @@
+use reqwest::{Client, Error as ReqwestError, StatusCode};
+use serde_json::{Error as SerdeJsonError, Value};
+use std::error::Error as StdError;
+use std::fmt;
+
+use crate::bitbucket::DEFAULT_USER_AGENT;
+
+const API_URL: &str = "https://dev.azure.com";
+const API_VERSION: &str = "7.1";
+// The most changes the diffs API returns per request, larger diffs are paged
+const PAGE_SIZE: usize = 2000;
+
+/// Represents errors that can occur while interacting with the Azure DevOps API, split
+/// by cause the same way as `BitbucketError`.
+#[derive(Debug)]
+pub enum AzureDevOpsError {
+    /// The personal access token was rejected (401) or lacks access to the repository (403).
+    Auth,
+    /// The organization, project, repository or branch does not exist (404).
+    NotFound,
+    /// The repository was found but has no branch by that name.
+    BranchNotFound(String),
+    /// Azure DevOps is throttling requests (429).
+    RateLimited,
+    /// Any other unsuccessful status code.
+    Status(StatusCode),
+    /// The request could not be sent or its body could not be read.
+    Network(ReqwestError),
+    /// The response body was not the JSON that was expected.
+    Parse(SerdeJsonError),
+    /// The async runtime behind the blocking wrappers could not be started.
+    Runtime(std::io::Error),
+}
+
+/// Connection details for the Azure DevOps Repos REST API
+pub struct AzureDevOps {
+    azure_org: String,
+    azure_project: String,
+    azure_repo: String,
+    azure_pat: String,
+    base_url: String,
+    user_agent: String,
+    client: Client
+}
+
+impl fmt::Display for AzureDevOpsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AzureDevOpsError::Auth => write!(f, "Azure DevOps rejected the personal access token, check azure_pat"),
+            AzureDevOpsError::NotFound => write!(f, "Azure DevOps could not find the organization, project, repository or branch requested"),
+            AzureDevOpsError::BranchNotFound(branch) => write!(f, "Branch {} not found in the repository", branch),
+            AzureDevOpsError::RateLimited => write!(f, "Azure DevOps is rate limiting requests, try again later"),
+            AzureDevOpsError::Status(status) => write!(f, "Request failed with status code: {}", status),
+            AzureDevOpsError::Network(err) => write!(f, "Request to Azure DevOps failed: {}", err),
+            AzureDevOpsError::Parse(err) => write!(f, "Unable to parse the Azure DevOps response: {}", err),
+            AzureDevOpsError::Runtime(err) => write!(f, "Failed to start async runtime for Azure DevOps requests: {}", err),
+        }
+    }
+}
+
+impl StdError for AzureDevOpsError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            AzureDevOpsError::Network(err) => Some(err),
+            AzureDevOpsError::Parse(err) => Some(err),
+            AzureDevOpsError::Runtime(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ReqwestError> for AzureDevOpsError {
+    fn from(err: ReqwestError) -> Self {
+        AzureDevOpsError::Network(err)
+    }
+}
+
+impl From<SerdeJsonError> for AzureDevOpsError {
+    fn from(err: SerdeJsonError) -> Self {
+        AzureDevOpsError::Parse(err)
+    }
+}
+
+impl AzureDevOps {
+    /// Creates a new `AzureDevOps` instance for one repository.
+    ///
+    /// # Arguments
+    ///
+    /// * `azure_org` - The organization, as in dev.azure.com/{organization}.
+    /// * `azure_project` - The project the repository belongs to.
+    /// * `azure_repo` - The repository name or ID.
+    /// * `azure_pat` - A personal access token with Code (Read) scope.
+    ///
+    /// # Returns
+    ///
+    /// A new `AzureDevOps` instance.
+    pub fn new(azure_org: String,
+                azure_project: String,
+                azure_repo: String,
+                azure_pat: String) -> Self {
+        let client = Client::new();
+        let base_url = API_URL.to_string();
+        let user_agent = DEFAULT_USER_AGENT.to_string();
+        Self { azure_org, azure_project, azure_repo, azure_pat, base_url, user_agent, client }
+    }
+
+    /// Replaces the `API_URL` requests are sent to, such as a local mock server in tests
+    /// or an Azure DevOps Server collection URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_url` - The URL the organization is found under, without a trailing slash.
+    ///
+    /// # Returns
+    ///
+    /// The same `AzureDevOps` instance, using the given base URL.
+    pub fn with_base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Replaces the `DEFAULT_USER_AGENT` header sent with each request, the same as
+    /// `Bitbucket::with_user_agent`.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_agent` - The User-Agent header value.
+    ///
+    /// # Returns
+    ///
+    /// The same `AzureDevOps` instance, sending the given User-Agent.
+    pub fn with_user_agent(mut self, user_agent: String) -> Self {
+        self.user_agent = user_agent;
+        self
+    }
+
+    /// Sends an HTTP GET request for a repository endpoint with the personal access token.
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint` - The path following the repository, e.g. `diffs/commits`.
+    /// * `query` - Query parameters, besides the API version which is always added.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the response body as JSON if the request was successful,
+    /// or an error if the request failed.
+    async fn send_http_request(&self, endpoint: &str, query: &[(&str, String)]) -> Result<Value, AzureDevOpsError> {
+        let url = format!("{}/{}/{}/_apis/git/repositories/{}/{}",
+            self.base_url, self.azure_org, self.azure_project, self.azure_repo, endpoint);
+
+        // Personal access tokens go in the password of basic authentication, with
+        // the username left empty.
+        let response = self
+            .client
+            .get(&url)
+            .query(query)
+            .query(&[("api-version", API_VERSION)])
+            .basic_auth("", Some(&self.azure_pat))
+            .header("User-Agent", &self.user_agent)
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(match status {
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => AzureDevOpsError::Auth,
+                StatusCode::NOT_FOUND => AzureDevOpsError::NotFound,
+                StatusCode::TOO_MANY_REQUESTS => AzureDevOpsError::RateLimited,
+                _ => AzureDevOpsError::Status(status),
+            });
+        }
+
+        let json_string = response.text().await?;
+        Ok(serde_json::from_str(&json_string)?)
+    }
+
+    /// Retrieves the difference between two branches, or two commits, from the
+    /// Azure DevOps diffs API.
+    ///
+    /// # Arguments
+    ///
+    /// * `feature_version` - The feature branch name, or the commit being deployed.
+    /// * `compare_version` - The branch name, or commit, to compare against.
+    /// * `version_type` - `branch` or `commit`, for both versions.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing a vector of status and path lines in the same form as
+    /// `Bitbucket::get_diff`, or an error if the operation failed.
+    pub async fn get_diff(
+        &self,
+        feature_version: &str,
+        compare_version: &str,
+        version_type: &str,
+    ) -> Result<Vec<String>, AzureDevOpsError> {
+        let mut diff_output: Vec<String> = Vec::new();
+        let mut skip: usize = 0;
+
+        // Azure DevOps diffs against the merge base of the two versions by default,
+        // so diffCommonCommit=false asks for the plain two-commit diff that git mode
+        // and Bitbucket mode use.
+        loop {
+            let query = [
+                ("baseVersion", compare_version.to_string()),
+                ("baseVersionType", version_type.to_string()),
+                ("targetVersion", feature_version.to_string()),
+                ("targetVersionType", version_type.to_string()),
+                ("diffCommonCommit", String::from("false")),
+                ("$top", PAGE_SIZE.to_string()),
+                ("$skip", skip.to_string()),
+            ];
+
+            let diff_page = self.send_http_request("diffs/commits", &query).await?;
+            let changes = diff_page["changes"].as_array().cloned().unwrap_or_default();
+
+            diff_output.extend(changes.iter().filter_map(diff_line));
+
+            if changes.len() < PAGE_SIZE || diff_page["allChangesIncluded"].as_bool() == Some(true) {
+                break;
+            }
+
+            skip += changes.len();
+        }
+
+        Ok(diff_output)
+    }
+
+    /// Retrieves the ID of the commit a branch points to.
+    ///
+    /// # Arguments
+    ///
+    /// * `branch` - The name of the branch.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the commit ID if successful, or an error if the operation failed.
+    pub async fn get_branch_commit_id(&self, branch: &str) -> Result<String, AzureDevOpsError> {
+        let branch_ref = format!("heads/{}", branch);
+        let refs = self.send_http_request("refs", &[("filter", branch_ref.clone())]).await?;
+
+        // The filter is a prefix match, so heads/feature also lists heads/feature-2
+        let full_ref = format!("refs/{}", branch_ref);
+        let matching_ref = refs["value"].as_array()
+            .and_then(|branch_refs| branch_refs.iter().find(|branch_ref| branch_ref["name"] == full_ref.as_str()));
+
+        match matching_ref.and_then(|branch_ref| branch_ref["objectId"].as_str()) {
+            Some(commit_id) => Ok(commit_id.to_string()),
+            None => Err(AzureDevOpsError::BranchNotFound(branch.to_string())),
+        }
+    }
+
+    /// Blocking version of `get_branch_commit_id`, see `get_diff_blocking`.
+    ///
+    /// # Arguments
+    ///
+    /// * `branch` - The name of the branch.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the commit ID if successful, or an error if the operation failed.
+    pub fn get_branch_commit_id_blocking(&self, branch: &str) -> Result<String, AzureDevOpsError> {
+        Self::blocking_runtime()?.block_on(self.get_branch_commit_id(branch))
+    }
+
+    /// Blocking version of `get_diff` for callers that don't run an async runtime of
+    /// their own, see `Bitbucket::get_diff_blocking`.
+    ///
+    /// # Arguments
+    ///
+    /// * `feature_version` - The feature branch name, or the commit being deployed.
+    /// * `compare_version` - The branch name, or commit, to compare against.
+    /// * `version_type` - `branch` or `commit`, for both versions.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing a vector of status and path lines, or an error if the
+    /// operation failed.
+    pub fn get_diff_blocking(
+        &self,
+        feature_version: &str,
+        compare_version: &str,
+        version_type: &str,
+    ) -> Result<Vec<String>, AzureDevOpsError> {
+        Self::blocking_runtime()?.block_on(self.get_diff(feature_version, compare_version, version_type))
+    }
+
+    /// Starts the single-threaded runtime the blocking requests run on.
+    fn blocking_runtime() -> Result<tokio::runtime::Runtime, AzureDevOpsError> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(AzureDevOpsError::Runtime)
+    }
+}
+
+/// Turns one entry of the diffs API `changes` into a status and path line, or None
+/// for folders, which git never lists, and for changes carrying none of the flags
+/// that alter a file's content or path (such as a lone `encoding` or `property`).
+///
+/// # Arguments
+///
+/// * `change` - One entry of the `changes` array.
+///
+/// # Returns
+///
+/// The line, e.g. `A       force-app/main/default/classes/A.cls`.
+fn diff_line(change: &Value) -> Option<String> {
+    let item = &change["item"];
+    if item["isFolder"].as_bool() == Some(true) || item["gitObjectType"] == "tree" {
+        return None;
+    }
+
+    // Paths come back rooted at the repository, e.g. /force-app/main/default/...
+    let path = item["path"].as_str()?.trim_start_matches('/');
+
+    // The change type is a comma separated set of flags, such as "edit, rename". Flags
+    // are matched whole, since "undelete" restores a file rather than deleting it.
+    let change_flags: Vec<&str> = change["changeType"].as_str().unwrap_or_default()
+        .split(',')
+        .map(|change_flag| change_flag.trim())
+        .collect();
+
+    let status = if change_flags.contains(&"delete") {
+        "D"
+    } else if change_flags.contains(&"rename") {
+        "R"
+    } else if change_flags.contains(&"add") || change_flags.contains(&"undelete") {
+        "A"
+    } else if change_flags.contains(&"edit") {
+        "M"
+    } else {
+        return None;
+    };
+
+    // Renames carry both paths, old then new, like git --name-status and Bitbucket mode
+    match change["sourceServerItem"].as_str() {
+        Some(old_path) if status == "R" => Some(format!("{}       {}       {}", status, old_path.trim_start_matches('/'), path)),
+        _ => Some(format!("{}       {}", status, path)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{Matcher, Server};
+
+    #[test]
+    fn get_diff_maps_changes_to_status_lines() {
+        let mut server = Server::new();
+        let diff = server.mock("GET", "/org/project/_apis/git/repositories/repo/diffs/commits")
+            .match_header("authorization", Matcher::Regex("^Basic ".to_string()))
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("baseVersion".to_string(), "qa".to_string()),
+                Matcher::UrlEncoded("targetVersion".to_string(), "feature/x".to_string()),
+                Matcher::UrlEncoded("baseVersionType".to_string(), "branch".to_string()),
+                Matcher::UrlEncoded("diffCommonCommit".to_string(), "false".to_string()),
+            ]))
+            .with_body(r#"{"allChangesIncluded": true, "changes": [
+                {"item": {"path": "/force-app/main/default/classes", "isFolder": true}, "changeType": "edit"},
+                {"item": {"path": "/force-app/main/default/classes/A.cls", "gitObjectType": "blob"}, "changeType": "add"},
+                {"item": {"path": "/force-app/main/default/classes/B.cls", "gitObjectType": "blob"}, "changeType": "delete"},
+                {"item": {"path": "/force-app/main/default/classes/New.cls", "gitObjectType": "blob"}, "changeType": "edit, rename", "sourceServerItem": "/force-app/main/default/classes/Old.cls"}
+            ]}"#)
+            .create();
+
+        let azure = AzureDevOps::new("org".to_string(), "project".to_string(), "repo".to_string(), "pat".to_string())
+            .with_base_url(format!("{}/", server.url()));
+        let diff_lines = azure.get_diff_blocking("feature/x", "qa", "branch").unwrap();
+
+        assert_eq!(diff_lines, vec![
+            "A       force-app/main/default/classes/A.cls".to_string(),
+            "D       force-app/main/default/classes/B.cls".to_string(),
+            "R       force-app/main/default/classes/Old.cls       force-app/main/default/classes/New.cls".to_string(),
+        ]);
+        diff.assert();
+    }
+
+    #[test]
+    fn change_type_flags_are_matched_whole() {
+        let change = |change_type: &str| serde_json::json!({"item": {"path": "/classes/A.cls"}, "changeType": change_type});
+
+        assert_eq!(diff_line(&change("undelete")), Some("A       classes/A.cls".to_string()));
+        assert_eq!(diff_line(&change("edit, undelete")), Some("A       classes/A.cls".to_string()));
+        assert_eq!(diff_line(&change("delete")), Some("D       classes/A.cls".to_string()));
+        assert_eq!(diff_line(&change("encoding")), None);
+        assert_eq!(diff_line(&change("")), None);
+    }
+
+    #[test]
+    fn get_diff_pages_through_large_diffs() {
+        let page_changes = |count: usize, offset: usize| -> String {
+            (0..count)
+                .map(|index| format!(r#"{{"item": {{"path": "/classes/C{}.cls"}}, "changeType": "edit"}}"#, offset + index))
+                .collect::<Vec<String>>()
+                .join(",")
+        };
+
+        let mut server = Server::new();
+        let first_page = server.mock("GET", "/org/project/_apis/git/repositories/repo/diffs/commits")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("$top".to_string(), PAGE_SIZE.to_string()),
+                Matcher::UrlEncoded("$skip".to_string(), "0".to_string()),
+            ]))
+            .with_body(format!(r#"{{"allChangesIncluded": false, "changes": [{}]}}"#, page_changes(PAGE_SIZE, 0)))
+            .create();
+        let second_page = server.mock("GET", "/org/project/_apis/git/repositories/repo/diffs/commits")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("$top".to_string(), PAGE_SIZE.to_string()),
+                Matcher::UrlEncoded("$skip".to_string(), PAGE_SIZE.to_string()),
+            ]))
+            .with_body(format!(r#"{{"allChangesIncluded": true, "changes": [{}]}}"#, page_changes(2, PAGE_SIZE)))
+            .create();
+
+        let azure = AzureDevOps::new("org".to_string(), "project".to_string(), "repo".to_string(), "pat".to_string())
+            .with_base_url(server.url());
+        let diff_lines = azure.get_diff_blocking("feature", "qa", "branch").unwrap();
+
+        assert_eq!(diff_lines.len(), PAGE_SIZE + 2);
+        assert_eq!(diff_lines[0], "M       classes/C0.cls");
+        assert_eq!(diff_lines[PAGE_SIZE + 1], format!("M       classes/C{}.cls", PAGE_SIZE + 1));
+        first_page.assert();
+        second_page.assert();
+    }
+
+    #[test]
+    fn get_branch_commit_id_needs_an_exact_branch_name() {
+        let mut server = Server::new();
+        server.mock("GET", "/org/project/_apis/git/repositories/repo/refs")
+            .match_query(Matcher::UrlEncoded("filter".to_string(), "heads/feature".to_string()))
+            .with_body(r#"{"value": [{"name": "refs/heads/feature-2", "objectId": "bbb"}, {"name": "refs/heads/feature", "objectId": "aaa"}]}"#)
+            .create();
+        server.mock("GET", "/org/project/_apis/git/repositories/repo/refs")
+            .match_query(Matcher::UrlEncoded("filter".to_string(), "heads/feat".to_string()))
+            .with_body(r#"{"value": [{"name": "refs/heads/feature", "objectId": "aaa"}]}"#)
+            .create();
+
+        let azure = AzureDevOps::new("org".to_string(), "project".to_string(), "repo".to_string(), "pat".to_string())
+            .with_base_url(server.url());
+
+        assert_eq!(azure.get_branch_commit_id_blocking("feature").unwrap(), "aaa");
+        assert!(matches!(azure.get_branch_commit_id_blocking("feat"), Err(AzureDevOpsError::BranchNotFound(_))));
+    }
+
+    #[test]
+    fn status_codes_map_to_error_variants() {
+        let mut server = Server::new();
+        server.mock("GET", Matcher::Any).with_status(401).create();
+
+        let azure = AzureDevOps::new("org".to_string(), "project".to_string(), "repo".to_string(), "pat".to_string())
+            .with_base_url(server.url());
+
+        assert!(matches!(azure.get_diff_blocking("feature", "qa", "branch"), Err(AzureDevOpsError::Auth)));
+    }
+}
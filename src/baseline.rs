@@ -0,0 +1,71 @@
+// The state file behind --incremental, which remembers per feature branch the
+// feature commit the last manifest was generated at and the changes it covered,
+// so the next run only has to diff from there.
+
+// FILE SYSTEM
+use std::fs as file_system;
+use std::path::Path;
+
+use serde_json::{json, Map, Value};
+
+/// Kept in the working path, beside the manifests it describes.
+pub const BASELINE_FILE_NAME: &str = ".sfmanifest-baseline.json";
+
+pub struct Baseline
+{
+	pub compare_branch: String,
+	pub commit: String,
+	pub changes: Vec<String>,
+}
+
+// Feature branch name to its baseline. A missing or unreadable file is the same
+// as having no baselines at all.
+fn read_state(state_file_path: &Path) -> Map<String, Value>
+{
+	return file_system::read_to_string(state_file_path)
+		.ok()
+		.and_then(|state_content| serde_json::from_str::<Value>(&state_content).ok())
+		.and_then(|state| state.as_object().cloned())
+		.unwrap_or_default();
+}
+
+pub fn read_baseline(state_file_path: &Path, feature_branch: &String) -> Option<Baseline>
+{
+	let state: Map<String, Value> = read_state(state_file_path);
+	let stored_baseline: &Value = state.get(feature_branch)?;
+
+	return Some(Baseline
+	{
+		compare_branch: stored_baseline["compare_branch"].as_str()?.to_string(),
+		commit: stored_baseline["commit"].as_str()?.to_string(),
+		changes: stored_baseline["changes"]
+			.as_array()?
+			.iter()
+			.filter_map(|change| change.as_str().map(|change| change.to_string()))
+			.collect(),
+	});
+}
+
+// Returns the state file content with the feature branch's baseline replaced,
+// leaving the baselines of other branches as they were.
+pub fn state_with_baseline(state_file_path: &Path, feature_branch: &String, baseline: &Baseline) -> String
+{
+	let mut state: Map<String, Value> = read_state(state_file_path);
+	state.insert(feature_branch.clone(), json!({
+		"compare_branch": baseline.compare_branch,
+		"commit": baseline.commit,
+		"changes": baseline.changes,
+	}));
+
+	return serde_json::to_string_pretty(&state).unwrap();
+}
+
+// Returns the state file content without the feature branch's baseline, or None
+// when there was no baseline to remove.
+pub fn state_without_baseline(state_file_path: &Path, feature_branch: &String) -> Option<String>
+{
+	let mut state: Map<String, Value> = read_state(state_file_path);
+	state.remove(feature_branch)?;
+
+	return Some(serde_json::to_string_pretty(&state).unwrap());
+}
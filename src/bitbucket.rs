@@ -1,14 +1,36 @@
-use reqwest::{Client, Error as ReqwestError};
-use serde_json::Value;
+use reqwest::{Client, Error as ReqwestError, StatusCode};
+use serde_json::{Error as SerdeJsonError, Value};
 use std::error::Error as StdError;
 use std::fmt;
 
 /// The base URL for the Bitbucket API.
 pub const API_URL: &str = "https://api.bitbucket.org/2.0/repositories";
 
-/// Represents errors that can occur while interacting with the Bitbucket API.
+/// The User-Agent sent with every request unless the `user_agent` configuration
+/// variable replaces it, so requests can be told apart in audit logs.
+pub const DEFAULT_USER_AGENT: &str = concat!("sfmanifest/", env!("CARGO_PKG_VERSION"));
+
+/// Represents errors that can occur while interacting with the Bitbucket API, split
+/// by cause so callers can decide how to report or retry each of them.
 #[derive(Debug)]
-pub struct CustomError(Box<dyn StdError>);
+pub enum BitbucketError {
+    /// The credentials were rejected (401) or lack access to the repository (403).
+    Auth,
+    /// The workspace, repository, branch or commit does not exist (404).
+    NotFound,
+    /// Bitbucket is throttling requests (429).
+    RateLimited,
+    /// Any other unsuccessful status code.
+    Status(StatusCode),
+    /// The branch exists but no commit could be read from the response.
+    CommitNotFound(String),
+    /// The request could not be sent or its body could not be read.
+    Network(ReqwestError),
+    /// The response body was not the JSON that was expected.
+    Parse(SerdeJsonError),
+    /// The async runtime behind the blocking wrappers could not be started.
+    Runtime(std::io::Error),
+}
 
 /// Authorization data structure for connecting to the Bitbucket API
 pub struct Bitbucket {
@@ -16,24 +38,46 @@ pub struct Bitbucket {
     bitbucket_app_password: String,
     bitbucket_workspace: String,
     bitbucket_repository: String,
+    base_url: String,
+    user_agent: String,
     client: Client
 }
 
-impl fmt::Display for CustomError {
+impl fmt::Display for BitbucketError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Custom Error: {}", self.0)
+        match self {
+            BitbucketError::Auth => write!(f, "Bitbucket rejected the credentials, check bitbucket_username and bitbucket_app_password"),
+            BitbucketError::NotFound => write!(f, "Bitbucket could not find the workspace, repository or branch requested"),
+            BitbucketError::RateLimited => write!(f, "Bitbucket is rate limiting requests, try again later"),
+            BitbucketError::Status(status) => write!(f, "Request failed with status code: {}", status),
+            BitbucketError::CommitNotFound(branch) => write!(f, "Commit ID not found for branch {}", branch),
+            BitbucketError::Network(err) => write!(f, "Request to Bitbucket failed: {}", err),
+            BitbucketError::Parse(err) => write!(f, "Unable to parse the Bitbucket response: {}", err),
+            BitbucketError::Runtime(err) => write!(f, "Failed to start async runtime for Bitbucket requests: {}", err),
+        }
     }
 }
 
-impl StdError for CustomError {
+impl StdError for BitbucketError {
     fn source(&self) -> Option<&(dyn StdError + 'static)> {
-        Some(&*self.0)
+        match self {
+            BitbucketError::Network(err) => Some(err),
+            BitbucketError::Parse(err) => Some(err),
+            BitbucketError::Runtime(err) => Some(err),
+            _ => None,
+        }
     }
 }
 
-impl From<ReqwestError> for CustomError {
+impl From<ReqwestError> for BitbucketError {
     fn from(err: ReqwestError) -> Self {
-        CustomError(Box::new(err))
+        BitbucketError::Network(err)
+    }
+}
+
+impl From<SerdeJsonError> for BitbucketError {
+    fn from(err: SerdeJsonError) -> Self {
+        BitbucketError::Parse(err)
     }
 }
 
@@ -52,7 +96,52 @@ impl Bitbucket {
                 bitbucket_workspace: String,
                 bitbucket_repository: String) -> Self {
         let client = Client::new();
-        Self {  bitbucket_username, bitbucket_app_password, bitbucket_workspace, bitbucket_repository, client }
+        let base_url = API_URL.to_string();
+        let user_agent = DEFAULT_USER_AGENT.to_string();
+        Self {  bitbucket_username, bitbucket_app_password, bitbucket_workspace, bitbucket_repository, base_url, user_agent, client }
+    }
+
+    /// Replaces the `API_URL` requests are sent to, such as a local mock server in tests
+    /// or a proxy in front of the Bitbucket API.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_url` - The repositories endpoint to use, without a trailing slash.
+    ///
+    /// # Returns
+    ///
+    /// The same `Bitbucket` instance, using the given base URL.
+    pub fn with_base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Replaces the `DEFAULT_USER_AGENT` header sent with each request, such as one an
+    /// API gateway or firewall allowlists.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_agent` - The User-Agent header value.
+    ///
+    /// # Returns
+    ///
+    /// The same `Bitbucket` instance, sending the given User-Agent.
+    pub fn with_user_agent(mut self, user_agent: String) -> Self {
+        self.user_agent = user_agent;
+        self
+    }
+
+    /// Builds the URL of an endpoint under the configured workspace and repository.
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint` - The path following the repository, e.g. `commits/main`.
+    ///
+    /// # Returns
+    ///
+    /// The full URL of the endpoint.
+    fn repository_url(&self, endpoint: &str) -> String {
+        format!("{}/{}/{}/{}", self.base_url, self.bitbucket_workspace, self.bitbucket_repository, endpoint)
     }
 
     /// Sends an HTTP GET request to the specified URL with the configured token.
@@ -65,7 +154,7 @@ impl Bitbucket {
     ///
     /// A Result containing the response body as a string if the request was successful,
     /// or an error if the request failed.
-    pub async fn send_http_request(&self, url: &str) -> Result<String, CustomError> {
+    pub async fn send_http_request(&self, url: &str) -> Result<String, BitbucketError> {
         let username = &self.bitbucket_username;
         let password = &self.bitbucket_app_password;
 
@@ -73,17 +162,19 @@ impl Bitbucket {
             .client
             .get(url)
             .basic_auth(username, Some(password))
-            .header("User-Agent", "Rust")
+            .header("User-Agent", &self.user_agent)
             .header("Accept", "application/json")
             .send()
             .await?;
 
         let status = response.status();
         if !status.is_success() {
-            return Err(CustomError(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Request failed with status code: {}", status),
-            ))));
+            return Err(match status {
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => BitbucketError::Auth,
+                StatusCode::NOT_FOUND => BitbucketError::NotFound,
+                StatusCode::TOO_MANY_REQUESTS => BitbucketError::RateLimited,
+                _ => BitbucketError::Status(status),
+            });
         }
 
         let json_string = response.text().await?;
@@ -105,22 +196,106 @@ impl Bitbucket {
         &self,
         feature_branch: &str,
         compare_branch: &str,
-    ) -> Result<Vec<String>, CustomError> {
+    ) -> Result<Vec<String>, BitbucketError> {
         let feature_branch_commit_id = self.get_latest_commit_id(feature_branch).await?;
         let compare_branch_commit_id = self.get_latest_commit_id(compare_branch).await?;
 
-        let url = format!(
-            "{}/{}/{}/diffstat/{}..{}",
-            API_URL, self.bitbucket_workspace, self.bitbucket_repository, feature_branch_commit_id, compare_branch_commit_id
-        );
+        self.get_commit_diff(&feature_branch_commit_id, &compare_branch_commit_id).await
+    }
+
+    /// Retrieves the difference between two commits from the Bitbucket API, without
+    /// resolving any branch names first.
+    ///
+    /// # Arguments
+    ///
+    /// * `feature_commit_id` - The hash of the commit being deployed.
+    /// * `compare_commit_id` - The hash of the commit to compare against.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing a vector of strings representing the differences
+    /// between the two commits, or an error if the operation failed.
+    pub async fn get_commit_diff(
+        &self,
+        feature_commit_id: &str,
+        compare_commit_id: &str,
+    ) -> Result<Vec<String>, BitbucketError> {
+        // Bitbucket reads `source..destination` as the changes the source introduces
+        // relative to the destination, which is what `git diff <compare> <feature>`
+        // reports in git mode. By default Bitbucket diffs against the merge base of
+        // the two commits instead (like `git diff <compare>...<feature>`), which can
+        // classify the same change differently, so topic=false asks for the plain
+        // two-commit diff that git mode uses.
+        let url = self.repository_url(&format!("diffstat/{}..{}?topic=false", feature_commit_id, compare_commit_id));
 
         let json_string = self.send_http_request(&url).await?;
 
-        let diff_stats: Value = serde_json::from_str(&json_string).map_err(|e| CustomError(Box::new(e)))?;
+        let diff_stats: Value = serde_json::from_str(&json_string)?;
 
         self.get_git_diff_response(diff_stats).await
     }
 
+    /// Blocking version of `get_diff` for callers that don't run an async runtime of
+    /// their own. A single-threaded runtime is started for the two commit lookups and
+    /// the diffstat request, and dropped once they complete.
+    ///
+    /// # Arguments
+    ///
+    /// * `feature_branch` - The name of the feature branch.
+    /// * `compare_branch` - The name of the branch to compare against.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing a vector of strings representing the differences
+    /// between the two branches, or an error if the operation failed.
+    pub fn get_diff_blocking(
+        &self,
+        feature_branch: &str,
+        compare_branch: &str,
+    ) -> Result<Vec<String>, BitbucketError> {
+        Self::blocking_runtime()?.block_on(self.get_diff(feature_branch, compare_branch))
+    }
+
+    /// Blocking version of `get_commit_diff`, see `get_diff_blocking`.
+    ///
+    /// # Arguments
+    ///
+    /// * `feature_commit_id` - The hash of the commit being deployed.
+    /// * `compare_commit_id` - The hash of the commit to compare against.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing a vector of strings representing the differences
+    /// between the two commits, or an error if the operation failed.
+    pub fn get_commit_diff_blocking(
+        &self,
+        feature_commit_id: &str,
+        compare_commit_id: &str,
+    ) -> Result<Vec<String>, BitbucketError> {
+        Self::blocking_runtime()?.block_on(self.get_commit_diff(feature_commit_id, compare_commit_id))
+    }
+
+    /// Blocking version of `get_latest_commit_id`, see `get_diff_blocking`.
+    ///
+    /// # Arguments
+    ///
+    /// * `branch` - The name of the branch.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the commit ID if successful, or an error if the operation failed.
+    pub fn get_latest_commit_id_blocking(&self, branch: &str) -> Result<String, BitbucketError> {
+        Self::blocking_runtime()?.block_on(self.get_latest_commit_id(branch))
+    }
+
+    /// Starts the single-threaded runtime the blocking requests run on.
+    fn blocking_runtime() -> Result<tokio::runtime::Runtime, BitbucketError> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(BitbucketError::Runtime)
+    }
+
     /// Parses the JSON response from the Bitbucket API and extracts the differences.
     ///
     /// # Arguments
@@ -134,7 +309,7 @@ impl Bitbucket {
     pub async fn get_git_diff_response(
         &self,
         diff_stats: Value,
-    ) -> Result<Vec<String>, CustomError> {
+    ) -> Result<Vec<String>, BitbucketError> {
         let mut diff_output: Vec<String> = Vec::new();
 
         if let Some(values) = diff_stats.get("values").and_then(|v| v.as_array()) {
@@ -144,14 +319,18 @@ impl Bitbucket {
                     Some("removed") => "D",
                     Some("modified") => "M",
                     Some("renamed") => "R",
-                    Some("merge conflict") => "M",
+                    // Same code git uses for unmerged paths, so conflicted files can be
+                    // reported rather than passing as plain modifications.
+                    Some("merge conflict") => "U",
                     Some("remote deleted") => "D",
                     Some("Unknown") => "?",
                     _ => "?",
                 };
 
                 if let (Some(old_file), Some(new_file)) = (diff["old"].as_object(), diff["new"].as_object()) {
-                    if diff["status"] == "R" {
+                    // Renames carry both paths, old then new, the same way git --name-status
+                    // prints them, so the old path still ends up as destructive.
+                    if diff["status"] == "renamed" {
                         diff_output.push(format!("{}       {}       {}", status, old_file["path"].as_str().unwrap_or_default(), new_file["path"].as_str().unwrap_or_default()));
                     } else {
                         diff_output.push(format!("{}       {}", status, new_file["path"].as_str().unwrap_or_default()));
@@ -176,22 +355,151 @@ impl Bitbucket {
     /// # Returns
     ///
     /// A Result containing the commit ID if successful, or an error if the operation failed.
-    pub async fn get_latest_commit_id(&self, branch: &str) -> Result<String, CustomError> {
-        let url = format!("{}/{}/{}/commits/{}", API_URL, self.bitbucket_workspace, self.bitbucket_repository, branch);
+    pub async fn get_latest_commit_id(&self, branch: &str) -> Result<String, BitbucketError> {
+        let url = self.repository_url(&format!("commits/{}", branch));
 
         let json_string = self.send_http_request(&url).await?;
-        let json: Value = serde_json::from_str(&json_string)
-            .map_err(|e| CustomError(Box::new(e)))?;
+        let json: Value = serde_json::from_str(&json_string)?;
 
         let commit_id = match json["values"][0]["hash"].as_str() {
             Some(commit_id) => commit_id.to_string(),
             None => {
-                return Err(CustomError(Box::new(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "Commit ID not found",
-                ))));
+                return Err(BitbucketError::CommitNotFound(branch.to_string()));
             }
         };
         Ok(commit_id)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{Matcher, Server};
+
+    fn commit_body(hash: &str) -> String {
+        format!(r#"{{"values": [{{"hash": "{}"}}]}}"#, hash)
+    }
+
+    #[test]
+    fn get_diff_uses_the_injected_base_url() {
+        let mut server = Server::new();
+        let feature_commit = server.mock("GET", "/ws/repo/commits/feature")
+            .match_header("authorization", Matcher::Regex("^Basic ".to_string()))
+            .with_body(commit_body("aaa"))
+            .create();
+        let compare_commit = server.mock("GET", "/ws/repo/commits/qa")
+            .with_body(commit_body("bbb"))
+            .create();
+        let diffstat = server.mock("GET", "/ws/repo/diffstat/aaa..bbb")
+            .match_query(Matcher::UrlEncoded("topic".to_string(), "false".to_string()))
+            .with_body(r#"{"values": [
+                {"status": "added", "old": null, "new": {"path": "force-app/main/default/classes/A.cls"}},
+                {"status": "removed", "old": {"path": "force-app/main/default/classes/B.cls"}, "new": null}
+            ]}"#)
+            .create();
+
+        let bitbucket = Bitbucket::new("user".to_string(), "password".to_string(), "ws".to_string(), "repo".to_string())
+            .with_base_url(format!("{}/", server.url()));
+        let diff = bitbucket.get_diff_blocking("feature", "qa").unwrap();
+
+        assert_eq!(diff, vec![
+            "A       force-app/main/default/classes/A.cls".to_string(),
+            "D       force-app/main/default/classes/B.cls".to_string(),
+        ]);
+        feature_commit.assert();
+        compare_commit.assert();
+        diffstat.assert();
+    }
+
+    #[test]
+    fn requests_send_the_configured_user_agent() {
+        let mut server = Server::new();
+        let default_agent = server.mock("GET", "/ws/repo/commits/qa")
+            .match_header("user-agent", DEFAULT_USER_AGENT)
+            .with_body(commit_body("aaa"))
+            .create();
+        let custom_agent = server.mock("GET", "/ws/repo/commits/uat")
+            .match_header("user-agent", "symmetry-ci/2")
+            .with_body(commit_body("bbb"))
+            .create();
+
+        let bitbucket = Bitbucket::new("user".to_string(), "password".to_string(), "ws".to_string(), "repo".to_string())
+            .with_base_url(server.url());
+        assert_eq!(bitbucket.get_latest_commit_id_blocking("qa").unwrap(), "aaa");
+
+        let bitbucket = bitbucket.with_user_agent("symmetry-ci/2".to_string());
+        assert_eq!(bitbucket.get_latest_commit_id_blocking("uat").unwrap(), "bbb");
+
+        default_agent.assert();
+        custom_agent.assert();
+    }
+
+    #[test]
+    fn get_commit_diff_skips_the_commit_lookups() {
+        let mut server = Server::new();
+        let commit_lookup = server.mock("GET", Matcher::Regex("^/ws/repo/commits/".to_string()))
+            .expect(0)
+            .create();
+        let diffstat = server.mock("GET", "/ws/repo/diffstat/abc1234..def5678")
+            .match_query(Matcher::UrlEncoded("topic".to_string(), "false".to_string()))
+            .with_body(r#"{"values": [
+                {"status": "modified", "old": {"path": "force-app/main/default/classes/A.cls"}, "new": {"path": "force-app/main/default/classes/A.cls"}}
+            ]}"#)
+            .create();
+
+        let bitbucket = Bitbucket::new("user".to_string(), "password".to_string(), "ws".to_string(), "repo".to_string())
+            .with_base_url(server.url());
+        let diff = bitbucket.get_commit_diff_blocking("abc1234", "def5678").unwrap();
+
+        assert_eq!(diff, vec!["M       force-app/main/default/classes/A.cls".to_string()]);
+        commit_lookup.assert();
+        diffstat.assert();
+    }
+
+    #[test]
+    fn diffstat_changes_sort_the_same_as_git_name_status() {
+        let mut server = Server::new();
+        server.mock("GET", "/ws/repo/commits/feature").with_body(commit_body("aaa")).create();
+        server.mock("GET", "/ws/repo/commits/qa").with_body(commit_body("bbb")).create();
+        server.mock("GET", "/ws/repo/diffstat/aaa..bbb")
+            .match_query(Matcher::UrlEncoded("topic".to_string(), "false".to_string()))
+            .with_body(r#"{"values": [
+                {"status": "added", "old": null, "new": {"path": "force-app/main/default/classes/Invoice.cls"}},
+                {"status": "modified", "old": {"path": "force-app/main/default/objects/Account/fields/Region__c.field-meta.xml"}, "new": {"path": "force-app/main/default/objects/Account/fields/Region__c.field-meta.xml"}},
+                {"status": "removed", "old": {"path": "force-app/main/default/triggers/Old.trigger"}, "new": null},
+                {"status": "renamed", "old": {"path": "force-app/main/default/classes/Before.cls"}, "new": {"path": "force-app/main/default/classes/After.cls"}}
+            ]}"#)
+            .create();
+
+        let bitbucket = Bitbucket::new("user".to_string(), "password".to_string(), "ws".to_string(), "repo".to_string())
+            .with_base_url(server.url());
+        let bitbucket_bundle = crate::manifest::tests::manifest_for(&bitbucket.get_diff_blocking("feature", "qa").unwrap().join("\n"));
+
+        let git_bundle = crate::manifest::tests::manifest_for(
+            "A\tforce-app/main/default/classes/Invoice.cls\n\
+            M\tforce-app/main/default/objects/Account/fields/Region__c.field-meta.xml\n\
+            D\tforce-app/main/default/triggers/Old.trigger\n\
+            R100\tforce-app/main/default/classes/Before.cls\tforce-app/main/default/classes/After.cls\n"
+        );
+
+        assert_eq!(bitbucket_bundle.manifest, git_bundle.manifest);
+        assert_eq!(bitbucket_bundle.destructive_manifest, git_bundle.destructive_manifest);
+        assert!(git_bundle.destructive_manifest.contains("<members>Before</members>"));
+    }
+
+    #[test]
+    fn status_codes_map_to_error_variants() {
+        let mut server = Server::new();
+        server.mock("GET", "/ws/repo/commits/locked").with_status(401).create();
+        server.mock("GET", "/ws/repo/commits/missing").with_status(404).create();
+        server.mock("GET", "/ws/repo/commits/busy").with_status(429).create();
+
+        let bitbucket = Bitbucket::new("user".to_string(), "password".to_string(), "ws".to_string(), "repo".to_string())
+            .with_base_url(server.url());
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        assert!(matches!(runtime.block_on(bitbucket.get_latest_commit_id("locked")), Err(BitbucketError::Auth)));
+        assert!(matches!(runtime.block_on(bitbucket.get_latest_commit_id("missing")), Err(BitbucketError::NotFound)));
+        assert!(matches!(runtime.block_on(bitbucket.get_latest_commit_id("busy")), Err(BitbucketError::RateLimited)));
+    }
+}
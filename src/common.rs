@@ -36,8 +36,6 @@
 #![allow(dead_code)]
 
 use std::{fs as filesystem};
-use std::path::Path;
-use std::env::current_dir as current_directory;
 
 use chrono::{DateTime, Local};
 
@@ -56,10 +54,18 @@ const NULL: u8 = b'\0';
 const LOG_LEVEL_INFO: u8 = 0x0;
 const LOG_LEVEL_ERROR: u8 = 0x1;
 const LOG_LEVEL_VERBOSE: u8 = 0x2;
+const LOG_LEVEL_WARNING: u8 = 0x3;
+
+// ANSI escape sequences used when printing to a color capable terminal
+const COLOR_RED: &str = "\x1b[31m";
+const COLOR_YELLOW: &str = "\x1b[33m";
+const COLOR_DIM: &str = "\x1b[2m";
+const COLOR_RESET: &str = "\x1b[0m";
 
 // TODO: Complete a macro that allows heap allocated strings to be 
 // merged together into a value similar to how print! can accomplish 
 // this but send to stdout. 
+#[allow(unused_macros)]
 macro_rules! string_merge {
 	($e:expr, String) => {
 		let string_message: &String = &$e;
@@ -72,15 +78,46 @@ pub struct LogMessage
 {
 	time: DateTime<Local>,
 	level: u8,
+	phase: String,
 	message: String,
 }
 
+fn level_name(level: u8) -> &'static str
+{
+	if level == LOG_LEVEL_INFO { return "INFO"; }
+	else if level == LOG_LEVEL_ERROR { return "ERROR"; }
+	else if level == LOG_LEVEL_VERBOSE { return "VERBOSE"; }
+	else if level == LOG_LEVEL_WARNING { return "WARNING"; }
+	else { return "UNKNOWN"; }
+}
+
+impl LogMessage
+{
+	// One self-contained JSON object per message, without the message's trailing
+	// new lines, for log aggregators that read JSON lines.
+	fn json_line(&self) -> String
+	{
+		let record = serde_json::json!({
+			"level": level_name(self.level),
+			"timestamp": self.time.to_rfc3339(),
+			"phase": self.phase,
+			"message": self.message.trim_end_matches(['\n', '\r']),
+		});
+
+		return format!("{}\n", record);
+	}
+}
+
 pub struct Logger
 {
 	// Overall configuration of the logger
 	pub print_all_on: bool,
 	pub file_path: String,
 	pub print_asap: bool, // Whether to print as soon as possible
+	pub color_on: bool, // Whether printed (never saved) messages are colored by level
+	pub json_lines_on: bool, // Whether messages are printed and saved as JSON lines
+	pub stderr_on: bool, // Whether messages are printed to standard error instead of standard out
+	phase: String, // What the program is doing, recorded with each message
 
 	// Enable flags for different settings of log
 	// messages at different levels
@@ -106,6 +143,10 @@ impl Logger
 			print_all_on: false,
 			file_path: String::new(),
 			print_asap: false,
+			color_on: false,
+			json_lines_on: false,
+			stderr_on: false,
+			phase: String::new(),
 
 			// print_info: false,
 			// save_info: false,
@@ -119,16 +160,60 @@ impl Logger
 	}
 
 	pub fn log(&mut self, message: &str, level: u8)
+	{
+		self.log_printed_as(message, level, level);
+	}
+
+	// Records the message at one level while printing it in the color of another
+	fn log_printed_as(&mut self, message: &str, level: u8, printed_level: u8)
 	{
 		let message_to_log = (*message).to_string(); // Whatttt?
-		if self.print_asap { print!("{}", message_to_log); }
 
 		let log_message: LogMessage = LogMessage
-		{ time: Local::now(), level: level, message: String::from(message_to_log) };
+		{ time: Local::now(), level, phase: self.phase.clone(), message: message_to_log };
+
+		if self.print_asap && self.json_lines_on { self.print_text(&log_message.json_line()); }
+		else if self.print_asap { self.print_message(&log_message.message, printed_level); }
 
 		self.messages.push(log_message); 
 	}
 
+	// Messages logged from here on are recorded as part of this phase
+	pub fn set_phase(&mut self, phase: &str)
+	{
+		self.phase = phase.to_string();
+	}
+
+	// Color codes wrap the message but not its trailing new lines, so that a
+	// colored line never bleeds into whatever is printed after it. Only the
+	// terminal ever sees them; saved messages stay plain.
+	fn print_message(&self, message: &str, level: u8)
+	{
+		let color = match level
+		{
+			LOG_LEVEL_ERROR => COLOR_RED,
+			LOG_LEVEL_WARNING => COLOR_YELLOW,
+			LOG_LEVEL_VERBOSE => COLOR_DIM,
+			_ => "",
+		};
+
+		if !self.color_on || color.len() == 0
+		{
+			self.print_text(message);
+			return;
+		}
+
+		let message_body = message.trim_end_matches(['\n', '\r']);
+		let trailing_new_lines = &message[message_body.len()..];
+		self.print_text(&format!("{}{}{}{}", color, message_body, COLOR_RESET, trailing_new_lines));
+	}
+
+	fn print_text(&self, text: &str)
+	{
+		if self.stderr_on { eprint!("{}", text); }
+		else { print!("{}", text); }
+	}
+
 	pub fn log_info(&mut self, message: &str)
 	{
 		self.log(message, LOG_LEVEL_INFO);
@@ -139,11 +224,34 @@ impl Logger
 		self.log(message, LOG_LEVEL_ERROR);
 	}
 
+	pub fn log_warning(&mut self, message: &str)
+	{
+		self.log(message, LOG_LEVEL_WARNING);
+	}
+
 	pub fn log_verbose(&mut self, message: &str)
 	{
 		self.log(message, LOG_LEVEL_VERBOSE);
 	}
 
+	// Logs an info message printed in the warning color, for output that needs
+	// attention but isn't a problem with the run (so --strict ignores it).
+	pub fn log_highlighted(&mut self, message: &str)
+	{
+		self.log_printed_as(message, LOG_LEVEL_INFO, LOG_LEVEL_WARNING);
+	}
+
+	// Used by --strict to find out whether a run logged anything that it carried on past
+	pub fn warning_count(&self) -> usize
+	{
+		return self.messages.iter().filter(|message| message.level == LOG_LEVEL_WARNING).count();
+	}
+
+	pub fn error_count(&self) -> usize
+	{
+		return self.messages.iter().filter(|message| message.level == LOG_LEVEL_ERROR).count();
+	}
+
 	pub fn publish(&mut self)
 	{
 		if self.file_path.len() == 0
@@ -155,16 +263,19 @@ impl Logger
 		let mut log_file_content: String = String::new();
 		for message in &self.messages
 		{
+			if self.json_lines_on
+			{
+				log_file_content.push_str(&message.json_line());
+				continue;
+			}
+
 			log_file_content.push_str(&message.time.to_rfc3339());
 
 			log_file_content.push(' ');
 			log_file_content.push('|');
 			log_file_content.push(' ');
 
-			if message.level == LOG_LEVEL_INFO { log_file_content.push_str("INFO"); }
-			else if message.level == LOG_LEVEL_ERROR { log_file_content.push_str("ERROR"); }
-			else if message.level == LOG_LEVEL_VERBOSE { log_file_content.push_str("VERBOSE"); }
-			else { log_file_content.push_str("UNKNOWN"); }
+			log_file_content.push_str(level_name(message.level));
 
 			log_file_content.push(' ');
 			log_file_content.push('|');
@@ -173,7 +284,7 @@ impl Logger
 			log_file_content.push_str(&message.message);
 		}
 
-		filesystem::write(&self.file_path, log_file_content);
+		let _ = filesystem::write(&self.file_path, log_file_content);
 	}
 }
 
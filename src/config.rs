@@ -1,16 +1,17 @@
 
 use crate::{Context, ToolContext};
 use crate::current_operating_system;
-use crate::slash;
+use crate::manifest::{git_uses_anonymous_https, git_uses_ssh};
+use crate::options::DEFAULT_COMPARE_BRANCH;
 
 // ENVIRONMENT
-use std::env::current_exe;
+use std::env::{current_dir, current_exe};
 
 // FILE SYSTEM
 use std::fs as file_system;
 use std::fs::File;
 use std::io::{Read, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 fn initialize_configurable_variables() -> Vec<String>
 {
@@ -20,6 +21,18 @@ fn initialize_configurable_variables() -> Vec<String>
 	variable_names.push(String::from("bitbucket_workspace"));
 	variable_names.push(String::from("bitbucket_repository"));
 	variable_names.push(String::from("working_path"));
+	variable_names.push(String::from("default_compare_branch"));
+	variable_names.push(String::from("shell_program"));
+	variable_names.push(String::from("shell_argument"));
+	variable_names.push(String::from("git_remote_url"));
+	variable_names.push(String::from("git_protocol"));
+	variable_names.push(String::from("always_include"));
+	variable_names.push(String::from("allowed_compare_branches"));
+	variable_names.push(String::from("azure_org"));
+	variable_names.push(String::from("azure_project"));
+	variable_names.push(String::from("azure_repo"));
+	variable_names.push(String::from("azure_pat"));
+	variable_names.push(String::from("user_agent"));
 	return variable_names;
 }
 
@@ -48,26 +61,84 @@ fn read_arg(variable_key_value_string: &str) -> (String, String) {
     }
 }
 
-pub fn config_root_path() -> String
+// The directory beside the executable, where config.txt has always been kept.
+// None when the executable's location can't be determined, as on some sandboxed
+// platforms, where it would otherwise resolve to a bogus relative path.
+fn executable_config_directory() -> Option<PathBuf>
 {
-	let mut length_of_exe_path_name: usize = 3;
-	if current_operating_system == "windows" { length_of_exe_path_name = 7; }
+	return current_exe()
+		.ok()
+		.and_then(|executable_path| executable_path.parent().map(|directory| directory.to_path_buf()))
+		.filter(|directory| !directory.as_os_str().is_empty());
+}
+
+// The per-user configuration directory: %APPDATA%\sfmanifest on Windows, and
+// $XDG_CONFIG_HOME/sfmanifest (or ~/.config/sfmanifest) elsewhere.
+fn user_config_directory() -> Option<PathBuf>
+{
+	let non_empty_variable = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty());
+
+	let base_directory: PathBuf = if current_operating_system == "windows"
+	{
+		PathBuf::from(non_empty_variable("APPDATA")?)
+	}
+	else if let Some(config_home) = non_empty_variable("XDG_CONFIG_HOME")
+	{
+		PathBuf::from(config_home)
+	}
+	else
+	{
+		PathBuf::from(non_empty_variable("HOME")?).join(".config")
+	};
 
-	let mut config_path = current_exe()
-		.unwrap_or_default()
-		.display()
-		.to_string();
+	return Some(base_directory.join("sfmanifest"));
+}
 
-	let mut config_path_revised: String = String::with_capacity(80);
-	let mut characters_left: usize = config_path.len() - 1;
-	for character in config_path.chars()
+pub fn config_root_path() -> String
+{
+	// Lets a run (or a test) keep its configuration somewhere other than next to
+	// the executable, which may be shared or read-only.
+	if let Some(config_directory) = std::env::var_os("SFMANIFEST_CONFIG_DIR")
 	{
-		config_path_revised.push(character);
-		if characters_left - length_of_exe_path_name == 0 { break; }
-		characters_left -= 1;
+		let mut config_path = config_directory.to_string_lossy().to_string();
+		if !config_path.ends_with(['/', '\\']) { config_path.push(crate::slash()); }
+		return config_path;
 	}
 
-	config_path = config_path_revised;
+	// SFMANIFEST_CONFIG_LOCATION=executable keeps config.txt beside the executable and
+	// =user keeps it in the per-user configuration directory. Left unset, an existing
+	// config.txt beside the executable is still used, and new installs (including
+	// read-only ones such as /usr/local/bin) get the per-user directory.
+	let config_location: String = std::env::var("SFMANIFEST_CONFIG_LOCATION").unwrap_or_default().to_lowercase();
+	let executable_directory = executable_config_directory();
+	let user_directory = user_config_directory();
+
+	let preferred_directory: Option<PathBuf> = match config_location.as_str()
+	{
+		"executable" => executable_directory.clone(),
+		"user" => user_directory.clone(),
+		_ =>
+		{
+			let executable_config_exists: bool = executable_directory.as_ref()
+				.is_some_and(|directory| directory.join("config.txt").exists());
+
+			if executable_config_exists { executable_directory.clone() } else { user_directory.clone() }
+		}
+	};
+
+	let config_directory: PathBuf = match preferred_directory.or(executable_directory).or(user_directory)
+	{
+		Some(directory) => directory,
+		None =>
+		{
+			let working_directory = current_dir().unwrap_or_default();
+			eprint!("WARNING: unable to locate a configuration directory, using {} for config.txt instead\n", working_directory.display());
+			working_directory
+		}
+	};
+
+	let mut config_path = config_directory.display().to_string();
+	if !config_path.ends_with(['/', '\\']) { config_path.push(crate::slash()); }
 	return config_path;
 }
 
@@ -86,6 +157,11 @@ fn get_config_file_content() -> String
 
 	// Create the file if it doesn't exist.
 	if !config_path_exists {
+        // The per-user configuration directory may not exist yet on a fresh install.
+        if let Some(config_directory) = Path::new(&config_path).parent() {
+            file_system::create_dir_all(config_directory).unwrap();
+        }
+
         // Create and initialize the file with default content if it doesn't exist
         let mut file = File::create(&config_path).unwrap();
         let default_content = r#"bitbucket_username=[enter value]
@@ -96,10 +172,36 @@ bitbucket_repository=[enter value]"#;
         file.write_all(default_content.as_bytes()).unwrap();
     }
 	
-	let mut config_file_content = String::with_capacity(2048);
+	let mut config_file_bytes: Vec<u8> = Vec::with_capacity(2048);
 	let mut config_file = File::open(config_path).unwrap();
-	config_file.read_to_string(&mut config_file_content).unwrap();
-	return config_file_content;
+	config_file.read_to_end(&mut config_file_bytes).unwrap();
+	return decode_config_content(&config_file_bytes);
+}
+
+// Editors on Windows may save config.txt with a byte order mark, which would
+// otherwise end up as part of the first key (\u{feff}bitbucket_username) so that
+// variable never matched, or as UTF-16 ("Unicode" in Notepad). Both are decoded
+// to plain text here, and bytes that aren't valid UTF-8 are replaced rather than
+// failing the read.
+fn decode_config_content(config_file_bytes: &[u8]) -> String
+{
+	let decode_utf16 = |content_bytes: &[u8], to_code_unit: fn([u8; 2]) -> u16| -> String
+	{
+		let code_units: Vec<u16> = content_bytes
+			.chunks_exact(2)
+			.map(|code_unit_bytes| to_code_unit([code_unit_bytes[0], code_unit_bytes[1]]))
+			.collect();
+		return String::from_utf16_lossy(&code_units);
+	};
+
+	if let Some(content_bytes) = config_file_bytes.strip_prefix(&[0xFF, 0xFE])
+	{ return decode_utf16(content_bytes, u16::from_le_bytes); }
+
+	if let Some(content_bytes) = config_file_bytes.strip_prefix(&[0xFE, 0xFF])
+	{ return decode_utf16(content_bytes, u16::from_be_bytes); }
+
+	let content_bytes: &[u8] = config_file_bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(config_file_bytes);
+	return String::from_utf8_lossy(content_bytes).into_owned();
 }
 
 /// Prompts the user to enter their Bitbucket configuration values.
@@ -122,6 +224,11 @@ bitbucket_repository=[enter value]"#;
 /// ```
 pub fn prompt_for_config_values(_general_context: &Context, tool_context: &mut ToolContext) 
 {
+	// Azure DevOps mode reads its own azure_* variables, which are reported as
+	// missing rather than asked for, and a --patch-file run needs no credentials.
+	if tool_context.command_parameters.contains_key("azure") { return; }
+	if tool_context.command_parameters.contains_key("patchfile") { return; }
+
 	let mut bitbucket_username = tool_context.configuration_variables.get("bitbucket_username")
     	.unwrap_or(&String::from("[enter value]")).to_string();
 	let mut bitbucket_app_password = tool_context.configuration_variables.get("bitbucket_app_password")
@@ -131,28 +238,33 @@ pub fn prompt_for_config_values(_general_context: &Context, tool_context: &mut T
 	let mut bitbucket_repository = tool_context.configuration_variables.get("bitbucket_repository")
 		.unwrap_or(&String::from("[enter value]")).to_string();
 
-	if bitbucket_username == "[enter value]" { 
+	// Fetching over SSH in git mode authenticates with the SSH agent, and a --public
+	// repository is fetched anonymously, so there's no username or app password to ask for.
+	let needs_no_credentials: bool = tool_context.command_parameters.contains_key("git")
+		&& (git_uses_ssh(tool_context) || git_uses_anonymous_https(tool_context));
+
+	if bitbucket_username == "[enter value]" && !needs_no_credentials { 
 		print!("Please enter your Bitbucket username: ");
 		bitbucket_username.clear();
 		std::io::stdout().flush().unwrap();
 		std::io::stdin().read_line(&mut bitbucket_username).unwrap();
 	}
 
-	if bitbucket_app_password == "[enter value]" {
+	if bitbucket_app_password == "[enter value]" && !tool_context.command_parameters.contains_key("bbpassword") && !needs_no_credentials {
 		bitbucket_app_password.clear();
 		print!("Please enter your Bitbucket app password: ");
 		std::io::stdout().flush().unwrap();
 		std::io::stdin().read_line(&mut bitbucket_app_password).unwrap();
 	}
 
-	if bitbucket_workspace == "[enter value]" {
+	if bitbucket_workspace == "[enter value]" && !tool_context.command_parameters.contains_key("bbworkspace") {
 		bitbucket_workspace.clear();
 		print!("Please enter your Bitbucket workspace: ");
 		std::io::stdout().flush().unwrap();
 		std::io::stdin().read_line(&mut bitbucket_workspace).unwrap();
 	}
 
-	if bitbucket_repository == "[enter value]" {
+	if bitbucket_repository == "[enter value]" && !tool_context.command_parameters.contains_key("bbrepository") {
 		bitbucket_repository.clear();
 		print!("Please enter your Bitbucket repository: ");
 		std::io::stdout().flush().unwrap();
@@ -179,7 +291,9 @@ pub fn load_variables(_general_context: &Context, tool_context: &mut ToolContext
 	if config_file_content.len() == 0
 	{ return; }
 
-	let config_file_content_lines: Vec<&str>= config_file_content.split("\n").collect();
+	// lines() also drops the \r of Windows line endings, which would otherwise be
+	// kept at the end of every value.
+	let config_file_content_lines: Vec<&str>= config_file_content.lines().collect();
 	for line in &config_file_content_lines
 	{
 		// Used to avoid if there's a line that contains only a new
@@ -211,12 +325,103 @@ pub fn load_variables(_general_context: &Context, tool_context: &mut ToolContext
 	}
 }
 
+// With --verbose, lists which of the Bitbucket variables config.txt provides, so a
+// missing or placeholder value shows up at the start of a run rather than as a
+// failure partway through it. Values themselves are never printed.
+pub fn report_variable_state(general_context: &mut Context, tool_context: &ToolContext)
+{
+	if !tool_context.command_parameters.contains_key("verbose") { return; }
+
+	let overrides = command_line_overrides();
+	let required_variables = if tool_context.command_parameters.contains_key("azure")
+	{ ["azure_org", "azure_project", "azure_repo", "azure_pat"] }
+	else
+	{ ["bitbucket_username", "bitbucket_app_password", "bitbucket_workspace", "bitbucket_repository"] };
+
+	let mut report: String = format!("Configuration loaded from {}:\n", config_file_path());
+	for variable_name in required_variables
+	{
+		let state: &str = match tool_context.configuration_variables.get(variable_name).map(|value| value.trim())
+		{
+			None | Some("") => "missing",
+			Some("[enter value]") => "placeholder",
+			Some(_) => "set",
+		};
+
+		let overridden: bool = overrides.iter()
+			.any(|(override_variable, parameter)| override_variable == variable_name && tool_context.command_parameters.contains_key(parameter));
+
+		if overridden { report.push_str(&format!("  {}: {} (overridden on the command line)\n", variable_name, state)); }
+		else { report.push_str(&format!("  {}: {}\n", variable_name, state)); }
+	}
+
+	general_context.logger.log_verbose(&report);
+}
+
+// Configuration variables that can be overridden for a single run from the
+// command line, paired with the command parameter that carries the override.
+fn command_line_overrides() -> Vec<(String, String)>
+{
+	let mut overrides: Vec<(String, String)> = Vec::with_capacity(8);
+	overrides.push((String::from("bitbucket_workspace"), String::from("bbworkspace")));
+	overrides.push((String::from("bitbucket_repository"), String::from("bbrepository")));
+	overrides.push((String::from("bitbucket_app_password"), String::from("bbpassword")));
+	return overrides;
+}
+
+// Replaces configuration variables in memory with any values given on the command
+// line. Nothing here is written back to config.txt.
+pub fn apply_command_line_overrides(tool_context: &mut ToolContext)
+{
+	for (variable_name, parameter_key) in command_line_overrides()
+	{
+		if let Some(override_value) = tool_context.command_parameters.get(&parameter_key)
+		{
+			tool_context.configuration_variables.insert(variable_name, override_value.clone());
+		}
+	}
+}
+
+// Settles which branch to compare against, in one place: the --branch flag wins,
+// then the default_compare_branch configuration variable, and finally the
+// compiled-in default. The result is stored as the branch command parameter.
+pub fn resolve_compare_branch(tool_context: &mut ToolContext)
+{
+	if tool_context.command_parameters.contains_key("branch") { return; }
+
+	let mut compare_branch: String = String::from(DEFAULT_COMPARE_BRANCH);
+	if let Some(configured_compare_branch) = tool_context.configuration_variables.get("default_compare_branch")
+		&& configured_compare_branch.trim().len() > 0
+		&& configured_compare_branch != "[enter value]"
+	{
+		compare_branch = configured_compare_branch.trim().to_string();
+	}
+
+	tool_context.command_parameters.insert(String::from("branch"), compare_branch);
+}
+
 fn set_variable(_general_context: &Context, 
 	tool_context: &mut ToolContext,
 	variable_argument: &String)
 {
 	let variable_arg_as_str = variable_argument.as_str();
 	let (key, value) = read_arg(variable_arg_as_str);
+
+	// Unknown keys are most likely typos (bitbucket_usrname), which would otherwise
+	// persist silently and never be read. They're still allowed unless --strict is
+	// set, in case an extra variable is intentional.
+	let known_variables = initialize_configurable_variables();
+	if !known_variables.contains(&key)
+	{
+		if tool_context.command_parameters.contains_key("strict")
+		{
+			print!("ERROR: {} is not a known configuration variable and was not set. Known variables are: {}\n", key, known_variables.join(", "));
+			return;
+		}
+
+		print!("WARNING: {} is not a known configuration variable, setting it anyway. Known variables are: {}\n", key, known_variables.join(", "));
+	}
+
 	tool_context.configuration_variables.insert(key, value);
 
 	write_variable_file(_general_context, tool_context);
@@ -241,12 +446,13 @@ fn write_variable_file(_general_context: &Context,
 
 fn get_all(_general_context: &Context, tool_context: &mut ToolContext)
 {
-	let keys = tool_context.configuration_variables.keys();
-	let keys_len = keys.len();
-	print!("keys: {}\n", keys_len);
+	// Only key=value lines go to standard out so the output can be piped into
+	// scripts, and keys are sorted so the output is the same from run to run.
+	let mut keys: Vec<&String> = tool_context.configuration_variables.keys().collect();
+	keys.sort();
+
 	for config_key in keys
 	{
-		let mut value: &String = &String::new();
 		// Special exception case for bitbucket_app_password for security purposes
 		if config_key == "bitbucket_app_password"
 		{
@@ -254,7 +460,7 @@ fn get_all(_general_context: &Context, tool_context: &mut ToolContext)
 		}
 		else
 		{
-			value = tool_context.configuration_variables.get_key_value(config_key).unwrap().1;
+			let value = tool_context.configuration_variables.get_key_value(config_key).unwrap().1;
 			print!("{}={}\n", config_key, value);
 		}
 		
@@ -283,4 +489,23 @@ pub fn configure(general_context: &Context, tool_context: &mut ToolContext)
 		tool_context.should_quit = true;
 		return;
 	}
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn decode_config_content_strips_byte_order_marks()
+	{
+		assert_eq!(decode_config_content(b"\xEF\xBB\xBFbitbucket_username=someone\r\n"), "bitbucket_username=someone\r\n");
+
+		let utf16_le: Vec<u8> = [0xFF, 0xFE].into_iter()
+			.chain("azure_org=org".encode_utf16().flat_map(|code_unit| code_unit.to_le_bytes()))
+			.collect();
+		assert_eq!(decode_config_content(&utf16_le), "azure_org=org");
+
+		assert_eq!(decode_config_content(b"git_protocol=ssh"), "git_protocol=ssh");
+	}
+}
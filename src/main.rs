@@ -1,27 +1,32 @@
 // ENVIRONMENT
-use std::env::args as command_line_arguments;
 use std::env::current_dir as current_working_directory;
 use std::env::consts::OS as current_operating_system;
 
 // TIME
-use std::time::{Instant,Duration};
+use std::time::Instant;
+
+// TERMINAL
+use std::io::IsTerminal;
 
 // MODULES
+mod azure;
+mod baseline;
 mod bitbucket;
 mod common;
 mod config;
 mod manifest;
 mod options;
+mod package;
+mod patch;
+mod preflight;
+mod project;
 mod system;
 
 // ELEGA CORE
 use common::{Context, Logger, TemporaryStorage};
 
-// JSON handling, used to parse sfdx-project.json
-use serde_json::{json, Value};
-
 // COLLECTION TYPES
-use std::collections::{HashSet, HashMap};
+use std::collections::HashMap;
 
 use crate::options::Automation;
 
@@ -31,6 +36,8 @@ pub struct ToolContext
 	should_quit: bool,
 
 	printing_on: bool,
+	progress_on: bool, // Whether the current phase of a run is reported on standard error
+	exit_code: i32, // Set when a run stops on an error, so scripts and CI can tell it failed
 
 	working_path: String,
 
@@ -40,6 +47,14 @@ pub struct ToolContext
 	time_snapshots: Vec<String>, // Captures performance related information and prints at end of program
 }
 
+impl Default for ToolContext
+{
+	fn default() -> Self
+	{
+		ToolContext::new()
+	}
+}
+
 impl ToolContext
 {
 	pub fn new() -> ToolContext
@@ -49,6 +64,8 @@ impl ToolContext
 			should_quit: false,
 
 			printing_on: true,
+			progress_on: false,
+			exit_code: 0,
 
 			working_path: current_working_directory().unwrap().display().to_string(),
 
@@ -90,7 +107,23 @@ pub fn configure_tool_context(tool_context: &mut ToolContext,
 {
 	if options.list_supported_mode
 	{
-		manifest::list_supported_metadata(tool_context);
+		manifest::list_supported_metadata(tool_context, options.format == Some(options::Format::Json));
+		tool_context.should_quit = true;
+		return;
+	}
+
+	if let Some(manifest_paths) = &options.diff_manifests
+	{
+		if !package::diff_manifests(&manifest_paths[0], &manifest_paths[1])
+		{ tool_context.exit_code = 1; }
+
+		tool_context.should_quit = true;
+		return;
+	}
+
+	if options.validate_types_mode
+	{
+		manifest::validate_metadata_types(tool_context);
 		tool_context.should_quit = true;
 		return;
 	}
@@ -105,9 +138,78 @@ pub fn configure_tool_context(tool_context: &mut ToolContext,
 		tool_context.command_parameters.insert(user_key, user_value);
 	}
 
+	// BITBUCKET WORKSPACE AND REPOSITORY
+	let workspace_key: String = String::from("bbworkspace");
+	let repository_key: String = String::from("bbrepository");
+
+	if let Some(workspace) = &options.bitbucket_workspace
+	{
+		tool_context.command_parameters.insert(workspace_key, workspace.clone());
+	}
+
+	if let Some(repository) = &options.bitbucket_repository
+	{
+		tool_context.command_parameters.insert(repository_key, repository.clone());
+	}
+
+	// BITBUCKET APP PASSWORD
+	let app_password_key: String = String::from("bbpassword");
+
+	if let Some(app_password) = &options.bitbucket_app_password
+	{
+		eprint!("WARNING: Passing --bitbucket-app-password on the command line can leak it into shell history and process listings.\n");
+		tool_context.command_parameters.insert(app_password_key, app_password.clone());
+	}
+
 	// COMPARISON BRANCH
 	let branch_key: String = String::from("branch");
-	tool_context.command_parameters.insert(branch_key, options.branch.clone());
+
+	if let Some(branch) = &options.branch
+	{
+		tool_context.command_parameters.insert(branch_key, branch.clone());
+	}
+
+	// SINCE DATE
+	let since_key: String = String::from("since");
+
+	if let Some(since) = &options.since
+	{
+		tool_context.command_parameters.insert(since_key, since.clone());
+	}
+
+	// COMMITS
+	let compare_commit_key: String = String::from("comparecommit");
+	let feature_commit_key: String = String::from("featurecommit");
+
+	if let (Some(compare_commit), Some(feature_commit)) = (&options.compare_commit, &options.feature_commit)
+	{
+		tool_context.command_parameters.insert(compare_commit_key, compare_commit.clone());
+		tool_context.command_parameters.insert(feature_commit_key, feature_commit.clone());
+	}
+
+	// SINCE LAST TAG
+	let since_last_tag_key: String = String::from("sincelasttag");
+
+	if options.since_last_tag
+	{
+		tool_context.command_parameters.insert(since_last_tag_key, String::from("--since-last-tag"));
+	}
+
+	// INCREMENTAL
+	let incremental_key: String = String::from("incremental");
+
+	if options.incremental
+	{
+		tool_context.command_parameters.insert(incremental_key, String::from("--incremental"));
+	}
+
+	// RESET BASELINE
+	let reset_baseline_key: String = String::from("resetbaseline");
+
+	if options.reset_baseline
+	{
+		tool_context.command_parameters.insert(reset_baseline_key, String::from("--reset-baseline"));
+	}
 
 	// STRING ONLY PRINTING
 	let string_only_key: String = String::from("stringonly");
@@ -117,6 +219,192 @@ pub fn configure_tool_context(tool_context: &mut ToolContext,
 		tool_context.command_parameters.insert(string_only_key, String::from("--string-only"));
 	}
 
+	// METADATA ROOT
+	let metadata_root_key: String = String::from("metadataroot");
+
+	if let Some(metadata_root) = &options.metadata_root
+	{
+		tool_context.command_parameters.insert(metadata_root_key, metadata_root.clone());
+	}
+
+	// MINIMUM API VERSION
+	let min_api_version_key: String = String::from("minapiversion");
+
+	if let Some(min_api_version) = &options.min_api_version
+	{
+		tool_context.command_parameters.insert(min_api_version_key, min_api_version.clone());
+	}
+
+	// SINGLE PACKAGE
+	let package_key: String = String::from("package");
+
+	if let Some(package) = &options.package
+	{
+		tool_context.command_parameters.insert(package_key, package.clone());
+	}
+
+	// OUTPUT FILE NAMES
+	let package_name_key: String = String::from("packagename");
+	let destructive_name_key: String = String::from("destructivename");
+
+	tool_context.command_parameters.insert(package_name_key, options.package_name.clone());
+	tool_context.command_parameters.insert(destructive_name_key, options.destructive_name.clone());
+
+	// DRY RUN
+	let dry_run_key: String = String::from("dryrun");
+
+	if options.dry_run
+	{
+		tool_context.command_parameters.insert(dry_run_key, String::from("--dry-run"));
+	}
+
+	// ALWAYS WRITE
+	let always_write_key: String = String::from("alwayswrite");
+
+	if options.always_write
+	{
+		tool_context.command_parameters.insert(always_write_key, String::from("--always-write"));
+	}
+
+	// CASE INSENSITIVE DEDUPLICATION
+	let dedupe_case_key: String = String::from("dedupecase");
+
+	if options.dedupe_case_insensitive
+	{
+		tool_context.command_parameters.insert(dedupe_case_key, String::from("--dedupe-case-insensitive"));
+	}
+
+	// NATURAL SORT
+	let natural_sort_key: String = String::from("naturalsort");
+
+	if options.natural_sort
+	{
+		tool_context.command_parameters.insert(natural_sort_key, String::from("--natural-sort"));
+	}
+
+	// INDENTATION
+	let indent_key: String = String::from("indent");
+	tool_context.command_parameters.insert(indent_key, options.indent.to_string());
+
+	// TRAILING NEW LINE
+	let trailing_newline_key: String = String::from("trailingnewline");
+
+	if options.trailing_newline
+	{
+		tool_context.command_parameters.insert(trailing_newline_key, String::from("--trailing-newline"));
+	}
+
+	// COMBINED OUTPUT
+	let combined_key: String = String::from("combined");
+
+	if options.combined
+	{
+		tool_context.command_parameters.insert(combined_key, String::from("--combined"));
+	}
+
+	// OUTPUT FORMATS
+	let output_formats_key: String = String::from("outputformats");
+
+	if options.output_format.len() > 0
+	{
+		let output_formats: Vec<String> = options.output_format.iter().map(|output_format| output_format.to_string()).collect();
+		tool_context.command_parameters.insert(output_formats_key, output_formats.join(","));
+	}
+
+	// KEEP FLOW VERSIONS
+	let keep_flow_versions_key: String = String::from("keepflowversions");
+
+	if options.keep_flow_versions
+	{
+		tool_context.command_parameters.insert(keep_flow_versions_key, String::from("--keep-flow-versions"));
+	}
+
+	// ALWAYS INCLUDE
+	let always_include_key: String = String::from("alwaysinclude");
+
+	if options.always_include.len() > 0
+	{
+		tool_context.command_parameters.insert(always_include_key, options.always_include.join("\n"));
+	}
+
+	// MEMBER PATTERNS
+	let exclude_pattern_key: String = String::from("excludepattern");
+	let include_pattern_key: String = String::from("includepattern");
+
+	if let Some(exclude_pattern) = &options.exclude_pattern
+	{
+		tool_context.command_parameters.insert(exclude_pattern_key, exclude_pattern.clone());
+	}
+
+	if let Some(include_pattern) = &options.include_pattern
+	{
+		tool_context.command_parameters.insert(include_pattern_key, include_pattern.clone());
+	}
+
+	// INCLUDE PARENT OBJECTS
+	let include_parent_objects_key: String = String::from("includeparentobjects");
+
+	if options.include_parent_objects
+	{
+		tool_context.command_parameters.insert(include_parent_objects_key, String::from("--include-parent-objects"));
+	}
+
+	// SUMMARY JSON
+	let summary_json_key: String = String::from("summaryjson");
+
+	if let Some(summary_json) = &options.summary_json
+	{
+		tool_context.command_parameters.insert(summary_json_key, summary_json.clone());
+	}
+
+	// DESTRUCTIVE STATUSES
+	let destructive_statuses_key: String = String::from("destructivestatuses");
+
+	if let Some(destructive_statuses) = &options.destructive_statuses
+	{
+		tool_context.command_parameters.insert(destructive_statuses_key, destructive_statuses.clone());
+	}
+
+	// FORCE
+	let force_key: String = String::from("force");
+
+	if options.force
+	{
+		tool_context.command_parameters.insert(force_key, String::from("--force"));
+	}
+
+	// INCLUDE GROUP PERMISSION SETS
+	let include_group_permission_sets_key: String = String::from("includegrouppermissionsets");
+
+	if options.include_group_permission_sets
+	{
+		tool_context.command_parameters.insert(include_group_permission_sets_key, String::from("--include-group-permission-sets"));
+	}
+
+	// ONLY DESTRUCTIVE
+	let only_destructive_key: String = String::from("onlydestructive");
+
+	if options.only_destructive
+	{
+		tool_context.command_parameters.insert(only_destructive_key, String::from("--only-destructive"));
+	}
+
+	// ONLY ADDITIVE
+	let only_additive_key: String = String::from("onlyadditive");
+
+	if options.only_additive
+	{
+		tool_context.command_parameters.insert(only_additive_key, String::from("--only-additive"));
+	}
+
+	// FAIL ON CONFLICT
+	let fail_on_conflict_key: String = String::from("failonconflict");
+
+	if options.fail_on_conflict
+	{
+		tool_context.command_parameters.insert(fail_on_conflict_key, String::from("--fail-on-conflict"));
+	}
+
 	// NO CLEAN?
 	let no_clean_key: String = String::from("noclean");
 
@@ -125,6 +413,86 @@ pub fn configure_tool_context(tool_context: &mut ToolContext,
 		tool_context.command_parameters.insert(no_clean_key, String::from("--noclean"));
 	}
 
+	// SSH REMOTE
+	let ssh_key: String = String::from("ssh");
+
+	if options.ssh
+	{
+		tool_context.command_parameters.insert(ssh_key, String::from("--ssh"));
+	}
+
+	// PUBLIC REPOSITORY
+	let public_key: String = String::from("public");
+
+	if options.public
+	{
+		tool_context.command_parameters.insert(public_key, String::from("--public"));
+	}
+
+	// BASE URL
+	let base_url_key: String = String::from("baseurl");
+
+	if let Some(base_url) = &options.base_url
+	{
+		tool_context.command_parameters.insert(base_url_key, base_url.clone());
+	}
+
+	// REPOSITORY PATH
+	let repo_path_key: String = String::from("repopath");
+
+	if let Some(repo_path) = &options.repo_path
+	{
+		tool_context.command_parameters.insert(repo_path_key, repo_path.clone());
+	}
+
+	// KEEP SOURCE
+	let keep_source_key: String = String::from("keepsource");
+
+	if let Some(keep_source) = &options.keep_source
+	{
+		tool_context.command_parameters.insert(keep_source_key, keep_source.clone());
+	}
+
+	// VERBOSE
+	let verbose_key: String = String::from("verbose");
+
+	if options.verbose
+	{
+		tool_context.command_parameters.insert(verbose_key, String::from("--verbose"));
+	}
+
+	// CONCURRENCY
+	let concurrency_key: String = String::from("concurrency");
+
+	if let Some(concurrency) = options.concurrency
+	{
+		tool_context.command_parameters.insert(concurrency_key, concurrency.to_string());
+	}
+
+	// PATCH FILE
+	let patch_file_key: String = String::from("patchfile");
+
+	if let Some(patch_file) = &options.patch_file
+	{
+		tool_context.command_parameters.insert(patch_file_key, patch_file.clone());
+	}
+
+	// LIST FILES
+	let list_files_key: String = String::from("listfiles");
+
+	if options.list_files
+	{
+		tool_context.command_parameters.insert(list_files_key, String::from("--list-files"));
+	}
+
+	// PREFLIGHT
+	let preflight_key: String = String::from("preflight");
+
+	if options.preflight
+	{
+		tool_context.command_parameters.insert(preflight_key, String::from("--preflight"));
+	}
+
 	// SUPPORTED
 	let supported_key: String = String::from("supported");
 
@@ -141,6 +509,14 @@ pub fn configure_tool_context(tool_context: &mut ToolContext,
 		tool_context.command_parameters.insert(git_key, String::from("--git"));
 	}
 
+	// AZURE DEVOPS
+	let azure_key: String = String::from("azure");
+
+	if options.automation == Automation::AzureDevOps
+	{
+		tool_context.command_parameters.insert(azure_key, String::from("--azure"));
+	}
+
 	// CONFIG SET
 	let config_set_key: String = String::from("variable_set");
 	let variable_to_set_available: bool = options.config_set.is_some();
@@ -151,6 +527,20 @@ pub fn configure_tool_context(tool_context: &mut ToolContext,
 		tool_context.command_parameters.insert(config_set_key, variable_set_value);
 	}
 	
+	// STRICT
+	let strict_key: String = String::from("strict");
+	if options.strict
+	{
+		tool_context.command_parameters.insert(strict_key, String::from("--strict"));
+	}
+
+	// MAXIMUM DESTRUCTIVE
+	let max_destructive_key: String = String::from("maxdestructive");
+	if let Some(max_destructive) = options.max_destructive
+	{
+		tool_context.command_parameters.insert(max_destructive_key, max_destructive.to_string());
+	}
+
 	// CONFIG GET ALL
 	let config_get_all_key: String = String::from("get_all");
 	if options.config_get_all
@@ -158,6 +548,14 @@ pub fn configure_tool_context(tool_context: &mut ToolContext,
 		tool_context.command_parameters.insert(config_get_all_key, String::from("--get-all"));
 	}
 
+	// SHELL
+	let shell_key: String = String::from("shell");
+
+	if let Some(shell) = &options.shell
+	{
+		tool_context.command_parameters.insert(shell_key, shell.to_string());
+	}
+
 	// FEATURE
 	let feature_key: String = String::from("feature");
 	let feature_available: bool = options.feature.is_some();
@@ -179,6 +577,13 @@ fn main()
 	// General context is used for the logger and may apply to usage of the
 	// TemporaryStorage struct, which can be used to hold bytes on the stack
 	let general_context: &mut Context = &mut configure_general_context();
+	general_context.logger.color_on = !options.no_color
+		&& std::env::var_os("NO_COLOR").is_none()
+		&& std::io::stdout().is_terminal();
+
+	// Messages logged before the manifest phases begin are recorded under startup
+	general_context.logger.json_lines_on = options.log_format == options::Format::Json;
+	general_context.logger.set_phase("startup");
 
 	// The ToolContext instance gets carried throughout the program just like the
 	// general context does... but it serves the purpose of holding all the config
@@ -187,6 +592,27 @@ fn main()
 
 	configure_tool_context(tool_context, &options);
 
+	// Progress goes to standard error so it never ends up in piped output, and
+	// is only shown when someone is actually watching the terminal.
+	tool_context.progress_on = !options.quiet && std::io::stderr().is_terminal();
+
+	// With --list-files, standard out is only the list of paths, so log messages go to
+	// standard error and command output isn't echoed at all.
+	if options.list_files
+	{
+		general_context.logger.stderr_on = true;
+		tool_context.printing_on = false;
+	}
+
+	// JSON lines are only readable if nothing else is interleaved with them, so
+	// command output isn't echoed either.
+	if general_context.logger.json_lines_on
+	{ tool_context.printing_on = false; }
+
+	// Standalone commands such as --validate-types can fail too
+	if tool_context.should_quit && tool_context.exit_code != 0
+	{ std::process::exit(tool_context.exit_code); }
+
 	if tool_context.should_quit
 	{ return; }
 
@@ -194,6 +620,7 @@ fn main()
 	// values such as the bitbucket_username (which is used in manifest command), 
 	// or other useful parameters that apply to other commands.
 	config::load_variables(general_context, tool_context);
+	config::report_variable_state(general_context, tool_context);
 
 	// If there are configuration commands to run, we're going to pause here
 	// to run them and then exit
@@ -206,10 +633,26 @@ fn main()
 	// been specified in command line args necessary for running, one last check
 	// will take place for checking config variables and will prompt the user to
 	// enter them if they're not in-memory.
-	config::prompt_for_config_values(general_context, tool_context);
+	// A preflight reports missing values rather than asking for them.
+	let preflight: bool = tool_context.command_parameters.contains_key("preflight");
 
-	// Main logic for manifest generation finally proceeds!
-	manifest::generate_manifest(general_context, tool_context);
+	if !preflight
+	{ config::prompt_for_config_values(general_context, tool_context); }
+
+	// Values given on the command line only apply to this run, so they're swapped
+	// in after anything that might write config.txt.
+	config::apply_command_line_overrides(tool_context);
+	config::resolve_compare_branch(tool_context);
+
+	if preflight
+	{
+		preflight::run_preflight(general_context, tool_context);
+	}
+	else
+	{
+		// Main logic for manifest generation finally proceeds!
+		manifest::generate_manifest(general_context, tool_context);
+	}
 
 	// The total run time of interest ends here, and the * 1000.0 converts this from f64 
 	// seconds expressed as milliseconds.
@@ -231,4 +674,6 @@ fn main()
 	// terminal from the general context logger.
 	general_context.logger.publish();
 
+	if tool_context.exit_code != 0
+	{ std::process::exit(tool_context.exit_code); }
 }
@@ -1,52 +1,170 @@
-
-
-use std::thread::current;
 use std::time::{Instant};
 
 // FILE SYSTEM
 use std::fs as file_system;
+use std::path::Path;
 
 // ENVIRONMENT
-use std::env::current_dir as current_working_directory;
 use std::env::join_paths;
-use tokio::runtime::Runtime;
 use std::env::consts::OS as current_operating_system;
 
 // COLLECTIONS
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::cmp::Ordering;
 
 // ELEGA CORE
 use crate::common::{Context};
 
+// Member name filtering for --include-pattern and --exclude-pattern
+use regex::Regex;
+
+// JSON handling, used for the combined manifest output
+use serde_json::json;
+
 // MULTI-CORE PARALLELISM
 use rayon::prelude::*;
 
 // ToolContext carries the main command line arguments and other
 // input parameters
-use crate::system::run_command;
+use crate::system::{run_command, run_command_with_status};
 use crate::configure_general_context;
 use crate::ToolContext;
 use crate::slash;
+use crate::azure::AzureDevOps;
 use crate::bitbucket::Bitbucket;
+use crate::project::{package_directory_path, source_api_version};
+use crate::package::element_values;
+use crate::patch::{fold_name_status_lines, name_status_lines};
+use crate::baseline::{Baseline, BASELINE_FILE_NAME, read_baseline, state_with_baseline, state_without_baseline};
 
 const MAXIMUM_DIFF_FILE_SIZE: usize = 5000;
-const DEFAULT_COMPARE_BRANCH: &str = "qa";
+// Well beyond two paths at the usual 4096 character path limit, so only corrupt or
+// binary input (such as a bad --from-file) ever reaches it.
+const MAXIMUM_DIFF_LINE_LENGTH: usize = 10000;
+// Used when sfdx-project.json doesn't give a sourceApiVersion
+const DEFAULT_API_VERSION: &str = "64.0";
+const DEFAULT_METADATA_ROOT: &str = "force-app/main/default";
+// Metadata-format folders left behind by older retrieves, recognized alongside the
+// source-format metadata root. Categories are laid out the same way beneath it.
+const METADATA_FORMAT_ROOT: &str = "unpackaged/";
 const FEATURE_BRANCH_TEMP_FOLDER: &str = "_feature_branch_temp";
 const COMPARE_BRANCH_TEMP_FOLDER: &str = "_compare_branch_temp";
 
+// Sub-folders of objects/<Object>/ whose members are formatted as Object.Name
+// and deploy under their own metadata type rather than CustomObject. Each entry
+// must also have a bucket in common_metadata_buckets.
+const OBJECT_CHILD_CATEGORIES: [&str; 9] = [
+	"businessProcesses",
+	"compactLayouts",
+	"fieldSets",
+	"fields",
+	"listViews",
+	"recordTypes",
+	"sharingReasons",
+	"validationRules",
+	"webLinks",
+];
+
+// Metadata API type names that a package.xml <name> element may hold. This is
+// not the full list Salesforce publishes, only the types relevant to what this
+// tool supports or is likely to support, and is used by --validate-types to
+// catch typos in common_metadata_buckets before they surface as deploy failures.
+const KNOWN_METADATA_TYPES: &[&str] = &[
+	"ApexClass",
+	"ApexComponent",
+	"ApexPage",
+	"ApexTestSuite",
+	"ApexTrigger",
+	"ApprovalProcess",
+	"AssignmentRules",
+	"AuraDefinitionBundle",
+	"AutoResponseRules",
+	"BusinessProcess",
+	"CompactLayout",
+	"ContentAsset",
+	"CustomApplication",
+	"CustomField",
+	"CustomLabels",
+	"CustomMetadata",
+	"CustomNotificationType",
+	"CustomObject",
+	"CustomObjectTranslation",
+	"CustomPermission",
+	"CustomSite",
+	"CustomTab",
+	"Dashboard",
+	"Document",
+	"DuplicateRule",
+	"EmailTemplate",
+	"EscalationRules",
+	"ExperienceBundle",
+	"ExternalCredential",
+	"FieldSet",
+	"FlexiPage",
+	"Flow",
+	"FlowDefinition",
+	"GlobalValueSet",
+	"Group",
+	"Layout",
+	"LightningComponentBundle",
+	"ListView",
+	"MatchingRules",
+	"NamedCredential",
+	"PathAssistant",
+	"PermissionSet",
+	"PermissionSetGroup",
+	"PlatformEventChannel",
+	"Profile",
+	"Queue",
+	"QuickAction",
+	"RecordType",
+	"RemoteSiteSetting",
+	"Report",
+	"ReportType",
+	"Role",
+	"SearchLayout",
+	"Settings",
+	"SharingReason",
+	"SharingRules",
+	"StandardValueSet",
+	"StaticResource",
+	"Translations",
+	"ValidationRule",
+	"WebLink",
+	"Workflow",
+];
+
+const COMBINED_MANIFEST_NAME: &str = "deployment.json";
+
 const WHITESPACE: char = ' ';
 
+// Diffs produced on Windows may separate path segments with backslashes, so every
+// parser treats either character as a separator.
+const PATH_SEPARATORS: [char; 2] = ['/', '\\'];
+
 pub struct ManifestBundle
 {
 	pub manifest: String,
 	pub destructive_manifest: String,
+
+	// Metadata categories found in the diff that have no bucket, so none of their
+	// files made it into either manifest.
+	pub unsupported_categories: BTreeSet<String>,
+
+	// Changed file paths under the metadata root, whether or not they parsed,
+	// for --list-files.
+	pub changed_files: BTreeSet<String>,
+
+	// (package.xml type name, members added, members deleted) for every metadata
+	// type in either manifest, in manifest order.
+	pub type_counts: Vec<(String, usize, usize)>,
 }
 
 impl ManifestBundle
 {
 	pub fn new() -> ManifestBundle
 	{
-		ManifestBundle { manifest: String::new(), destructive_manifest: String::new() }
+		ManifestBundle { manifest: String::new(), destructive_manifest: String::new(), unsupported_categories: BTreeSet::new(), changed_files: BTreeSet::new(), type_counts: Vec::new() }
 	}
 }
 
@@ -60,6 +178,13 @@ pub struct MetadataBucket
 	pub files: HashSet<String>,
 	pub destructive_files: HashSet<String>,
 	pub bundle: bool,
+
+	// When enabled, member names differing only by case are collapsed into
+	// whichever casing was inserted first. The lowercased sets track what has
+	// already been seen.
+	pub case_insensitive: bool,
+	folded_files: HashSet<String>,
+	folded_destructive_files: HashSet<String>,
 }
 
 impl MetadataBucket
@@ -78,7 +203,36 @@ impl MetadataBucket
 			//
 			// We'd ignore the .js file above and simply take 'ComponentName' as the bundle name
 			// to retrieve, and that's what makes its way into the manifest.
-			bundle: bundle, 
+			bundle, 
+
+			case_insensitive: false,
+			folded_files: HashSet::new(),
+			folded_destructive_files: HashSet::new(),
+		}
+	}
+
+	pub fn insert_file(&mut self, member_name: String)
+	{
+		if self.case_insensitive && !self.folded_files.insert(member_name.to_lowercase())
+		{ return; }
+
+		self.files.insert(member_name);
+	}
+
+	pub fn insert_destructive_file(&mut self, member_name: String)
+	{
+		if self.case_insensitive && !self.folded_destructive_files.insert(member_name.to_lowercase())
+		{ return; }
+
+		self.destructive_files.insert(member_name);
+	}
+
+	pub fn insert_member(&mut self, member_name: String, change_kind: ChangeKind)
+	{
+		match change_kind
+		{
+			ChangeKind::Constructive => self.insert_file(member_name),
+			ChangeKind::Destructive => self.insert_destructive_file(member_name),
 		}
 	}
 }
@@ -102,9 +256,122 @@ fn create_new_folder(working_path: &String,
 	else if current_operating_system == "windows" { path = path.replace(";", ""); }
 
 	let path_cloned = path.clone();
-	print!("path_cloned: {}\n", path_cloned);
-	let _feature_folder_result = file_system::create_dir(path).unwrap_or_default();
-	return String::from(path_cloned);
+	file_system::create_dir(path).unwrap_or_default();
+	return path_cloned;
+}
+
+// Type:Member pairs to always add to package.xml, from the comma separated
+// always_include configuration variable followed by any --always-include options.
+fn always_include_components(general_context: &mut Context, tool_context: &ToolContext) -> Vec<(String, String)>
+{
+	let mut component_entries: Vec<String> = Vec::with_capacity(16);
+
+	if variable_is_set(tool_context, "always_include")
+	{
+		let configured_entries = tool_context.configuration_variables.get("always_include").unwrap();
+		component_entries.extend(configured_entries.split(',').map(|entry| entry.to_string()));
+	}
+
+	if let Some(option_entries) = tool_context.command_parameters.get("alwaysinclude")
+	{
+		component_entries.extend(option_entries.split('\n').map(|entry| entry.to_string()));
+	}
+
+	let mut components: Vec<(String, String)> = Vec::with_capacity(component_entries.len());
+	for component_entry in &component_entries
+	{
+		if component_entry.trim().len() == 0 { continue; }
+
+		match component_entry.split_once(':')
+		{
+			Some((type_name, member_name)) if type_name.trim().len() > 0 && member_name.trim().len() > 0 =>
+				components.push((type_name.trim().to_string(), member_name.trim().to_string())),
+			_ => general_context.logger.log_warning(
+				&format!("WARNING: Always include entry {} is not in Type:Member form and was ignored.\n", component_entry.trim())
+			),
+		}
+	}
+
+	return components;
+}
+
+// The permission sets bundled by a permission set group, read from the group's
+// definition in the fetched source. Deploying a group whose permission sets aren't
+// in the org (or in the same deploy) fails, so with --include-group-permission-sets
+// they are listed alongside it.
+fn group_permission_sets(general_context: &mut Context,
+	source_path: &String,
+	standard_folder: &String,
+	group_name: &String) -> Vec<String>
+{
+	let group_file_path = Path::new(source_path)
+		.join(format!("{}permissionsetgroups/{}.permissionsetgroup-meta.xml", standard_folder, group_name));
+
+	match file_system::read_to_string(&group_file_path)
+	{
+		Ok(group_definition) => return element_values(&group_definition, "permissionSets"),
+		Err(read_error) =>
+		{
+			general_context.logger.log_warning(
+				&format!("WARNING: Unable to read {} ({}), its permission sets have not been included.\n", group_file_path.display(), read_error)
+			);
+			return Vec::new();
+		}
+	}
+}
+
+// Whether git orchestration fetches from Bitbucket over SSH, either with --ssh or
+// with the git_protocol configuration variable set to ssh.
+pub fn git_uses_ssh(tool_context: &ToolContext) -> bool
+{
+	return tool_context.command_parameters.contains_key("ssh")
+		|| tool_context.configuration_variables.get("git_protocol").is_some_and(|protocol| protocol.trim() == "ssh");
+}
+
+// Whether git orchestration fetches a public repository with --public, which
+// needs no credentials at all.
+pub fn git_uses_anonymous_https(tool_context: &ToolContext) -> bool
+{
+	return tool_context.command_parameters.contains_key("git")
+		&& tool_context.command_parameters.contains_key("public");
+}
+
+// Git orchestration works with any git server, Bitbucket is only the default when
+// no git_remote_url is configured. Over SSH the user's SSH agent authenticates,
+// so no username goes into the URL; over HTTPS the username comes from config,
+// or --bitbucket-user when config has none. A --public repository is fetched
+// anonymously, with no username at all.
+pub fn git_origin_url(tool_context: &ToolContext) -> String
+{
+	if variable_is_set(tool_context, "git_remote_url")
+	{
+		return tool_context.configuration_variables.get("git_remote_url").unwrap().trim().to_string();
+	}
+
+	let empty_value: String = String::new();
+	let bitbucket_workspace: &String = tool_context.configuration_variables.get("bitbucket_workspace").unwrap_or(&empty_value);
+	let bitbucket_repository: &String = tool_context.configuration_variables.get("bitbucket_repository").unwrap_or(&empty_value);
+
+	if git_uses_ssh(tool_context)
+	{
+		return format!("git@bitbucket.org:{}/{}.git", bitbucket_workspace, bitbucket_repository);
+	}
+
+	if git_uses_anonymous_https(tool_context)
+	{
+		return format!("https://bitbucket.org/{}/{}.git", bitbucket_workspace, bitbucket_repository);
+	}
+
+	let bitbucket_username: &String = if variable_is_set(tool_context, "bitbucket_username")
+	{
+		tool_context.configuration_variables.get("bitbucket_username").unwrap()
+	}
+	else
+	{
+		tool_context.command_parameters.get("bbuser").unwrap_or(&empty_value)
+	};
+
+	return format!("https://{}@bitbucket.org/{}/{}.git", bitbucket_username, bitbucket_workspace, bitbucket_repository);
 }
 
 fn run_pull(tool_context: &mut ToolContext,
@@ -113,34 +380,25 @@ fn run_pull(tool_context: &mut ToolContext,
 	let general_context = &mut configure_general_context();
 	general_context.logger.file_path = general_context.logger.file_path.replace("log.txt", "git_log.txt");
 	
-	let bitbucket_username: &String = tool_context.configuration_variables.get_key_value("bitbucket_username").unwrap().1;
-	let bitbucket_workspace: &String = tool_context.configuration_variables.get_key_value("bitbucket_workspace").unwrap().1;
-	let bitbucket_repository: &String = tool_context.configuration_variables.get_key_value("bitbucket_repository").unwrap().1;
-
 	let git_init_command: &String = &String::from("git init");
-	let origin_url: String = format!("https://{}@bitbucket.org/{}/{}.git", bitbucket_username, 
-		bitbucket_workspace, 
-		bitbucket_repository);
+	let origin_url: String = git_origin_url(tool_context);
 	let git_remote_add_origin_command = &format!("git remote add origin {}", origin_url);
 	
 	let git_fetch_command = &String::from("git fetch");
 	let git_checkout_branch_command = &format!("git checkout -q {}", branch_name);
 
-	print!("repo_path: {}\n", repo_path);
+	if tool_context.printing_on
+	{ print!("repo_path: {}\n", repo_path); }
 
-	// Empty ToolContext that's created as a part of reqeuired arguments...
-	// but this isn't used in this case and doesn't really matter for our
-	// purposes
-	let empty_tool_context: &mut ToolContext = &mut ToolContext::new();
-
-	run_command(general_context, empty_tool_context, repo_path, git_init_command);
-	run_command(general_context, empty_tool_context, repo_path, git_remote_add_origin_command);
-	run_command(general_context, empty_tool_context, repo_path, git_fetch_command);
-	run_command(general_context, empty_tool_context, repo_path, git_checkout_branch_command);
+	// The tool context passed in here is already a per-thread clone, so it is
+	// safe to hand to run_command, which reads the configured shell from it.
+	run_command(general_context, tool_context, repo_path, git_init_command);
+	run_command(general_context, tool_context, repo_path, git_remote_add_origin_command);
+	run_command(general_context, tool_context, repo_path, git_fetch_command);
+	run_command(general_context, tool_context, repo_path, git_checkout_branch_command);
 }
 
 pub fn pull_branch_details(tool_context: &mut ToolContext,
-	bitbucket_username: String, 
 	repository_info: &RepositoryInfo)
 {
 	let working_path: &String = &tool_context.working_path;
@@ -148,45 +406,138 @@ pub fn pull_branch_details(tool_context: &mut ToolContext,
 	run_pull(tool_context, &repository_info.folder_path_as_string, &repository_info.branch_name);
 }
 
-fn branch_names(general_context: &mut Context, tool_context: &mut ToolContext) -> (String, String)
+pub fn branch_names(general_context: &mut Context, tool_context: &ToolContext) -> Option<(String, String)>
 {
 	// First, determine the feature branch and compare branch. How the feature branch differs from the compare branch
-	// determines which files will make their way into a manifest
-	let mut feature_branch: &String = &String::from("");
-	let (standard_out_from_git, standard_error_from_git) = run_command(
-		general_context, 
-		tool_context,
-		&tool_context.working_path.clone(), //  TODO: See if clone is avoidable
-		&String::from("git symbolic-ref --short -q HEAD")
-	);
-	let feature_branch_from_git = &standard_out_from_git.clone();
+	// determines which files will make their way into a manifest.
+	//
+	// Explicit commits are used exactly as given. There's no branch to resolve, and
+	// nothing to check against allowed_compare_branches.
+	if let (Some(feature_commit), Some(compare_commit)) = (tool_context.command_parameters.get("featurecommit"), tool_context.command_parameters.get("comparecommit"))
+	{
+		general_context.logger.log_info(&format!("feature commit: {}\n", feature_commit));
+		general_context.logger.log_info(&format!("compare commit: {}\n", compare_commit));
+		return Some((feature_commit.clone(), compare_commit.clone()));
+	}
 
-	if tool_context.command_parameters.contains_key("feature")
+	// When the feature branch is given explicitly, the local working tree is never
+	// consulted, so both branches are resolved purely from the remote. This allows
+	// running from a CI runner with a bare or detached checkout. Without --feature,
+	// a branch named by the CI server comes next, and the checked out branch last.
+	let current_branch: Option<String> = if tool_context.command_parameters.contains_key("feature")
 	{
-		feature_branch = &tool_context.command_parameters.get_key_value("feature").unwrap().1;
+		None
 	}
-	else // If no branch specified in argument, check current working directory for branch using 'git branch'
+	else if let Some((variable_name, ci_branch)) = ci_feature_branch(|variable_name| std::env::var(variable_name).ok())
 	{
-		if feature_branch_from_git.len() > 0
-		{
-			feature_branch = &feature_branch_from_git;
-		}
-		
-		if standard_error_from_git.len() > 0
+		general_context.logger.log_info(&format!("Using the feature branch from {}\n", variable_name));
+		Some(ci_branch)
+	}
+	else
+	{
+		Some(current_git_branch(general_context, tool_context))
+	};
+
+	let (feature_branch, compare_branch) = select_branch_names(tool_context, current_branch.as_deref());
+	general_context.logger.log_info(&format!("feature branch: {}\n", feature_branch));
+	general_context.logger.log_info(&format!("compare_branch: {}\n", compare_branch));
+
+	if let Err(allowed_branches) = check_compare_branch_allowed(tool_context, &compare_branch)
+	{
+		general_context.logger.log_error(
+			&format!("ERROR: {} is not an allowed compare branch (allowed: {}). Run with --force to compare against it anyway.\n", compare_branch, allowed_branches.join(", "))
+		);
+		return None;
+	}
+
+	return Some((feature_branch, compare_branch));
+}
+
+// With allowed_compare_branches configured (e.g. qa,uat,main), a manifest can only be
+// generated against one of those branches unless --force is given, so an accidental
+// diff against some other branch can't produce a nonsense manifest. On rejection the
+// allowed branches are returned for the error message.
+fn check_compare_branch_allowed(tool_context: &ToolContext, compare_branch: &String) -> Result<(), Vec<String>>
+{
+	if tool_context.command_parameters.contains_key("force") || !variable_is_set(tool_context, "allowed_compare_branches")
+	{
+		return Ok(());
+	}
+
+	let allowed_branches: Vec<String> = tool_context.configuration_variables.get("allowed_compare_branches").unwrap()
+		.split(',')
+		.map(|branch_name| branch_name.trim().to_string())
+		.filter(|branch_name| branch_name.len() > 0)
+		.collect();
+
+	if allowed_branches.contains(compare_branch) { return Ok(()); }
+
+	return Err(allowed_branches);
+}
+
+// CI servers usually build a detached checkout, where git symbolic-ref has no
+// branch to report, but name the branch being built in an environment variable.
+// GITHUB_HEAD_REF is only set (to the source branch) for pull requests, so
+// GITHUB_REF_NAME covers pushes.
+const CI_BRANCH_VARIABLES: [&str; 4] = ["BITBUCKET_BRANCH", "CI_COMMIT_REF_NAME", "GITHUB_HEAD_REF", "GITHUB_REF_NAME"];
+
+// The first CI branch variable with a value, along with the variable's name.
+fn ci_feature_branch(read_variable: impl Fn(&str) -> Option<String>) -> Option<(&'static str, String)>
+{
+	for variable_name in CI_BRANCH_VARIABLES
+	{
+		if let Some(branch_name) = read_variable(variable_name)
+			&& branch_name.trim().len() > 0
 		{
-			print!("WARNING: An error was encountered when trying to retrieve the current branch.\n\n{}\n", standard_error_from_git);
+			return Some((variable_name, branch_name.trim().to_string()));
 		}
 	}
-	print!("feature branch: {}\n", feature_branch);
 
-	let mut compare_branch: &String = &String::from(DEFAULT_COMPARE_BRANCH); // Default
-	if tool_context.command_parameters.contains_key("branch")
+	return None;
+}
+
+// The branch checked out in the working path, or an empty string when HEAD is
+// detached or the repository path isn't a git repository.
+fn current_git_branch(general_context: &mut Context, tool_context: &ToolContext) -> String
+{
+	let (current_branch_output, standard_error_from_git) = run_command(
+		general_context, 
+		tool_context,
+		repository_path(tool_context),
+		&String::from("git symbolic-ref --short -q HEAD")
+	);
+
+	if standard_error_from_git.len() > 0
 	{
-		compare_branch = &tool_context.command_parameters.get_key_value("branch").unwrap().1;
+		general_context.logger.log_warning(&format!("WARNING: An error was encountered when trying to retrieve the current branch.\n\n{}\n", standard_error_from_git));
 	}
-	print!("compare_branch: {}\n", compare_branch);
 
-	return (feature_branch.clone(), compare_branch.clone());
+	// Standard out carries a trailing new line that must not end up in the branch name
+	return current_branch_output.trim().to_string();
+}
+
+// The --feature flag wins over the branch checked out in the working path. The
+// compare branch default is already settled by config::resolve_compare_branch, so
+// the parameter is always present by the time a manifest is generated.
+fn select_branch_names(tool_context: &ToolContext, current_branch: Option<&str>) -> (String, String)
+{
+	let feature_branch: String = match tool_context.command_parameters.get("feature")
+	{
+		Some(feature_branch) => feature_branch.clone(),
+		None => current_branch.unwrap_or_default().to_string(),
+	};
+
+	let compare_branch: String = tool_context.command_parameters.get("branch").unwrap().clone();
+
+	return (feature_branch, compare_branch);
+}
+
+// Temp folders are suffixed with the process id, so runs sharing a working
+// directory (matrix CI jobs in one checkout, for example) each get their own
+// folders and clean up only those.
+fn temp_folder_name(temp_folder_base_name: &str) -> String
+{
+	return format!("{}_{}", temp_folder_base_name, std::process::id());
 }
 
 fn initialize_repository_information(general_context: &mut Context,
@@ -196,13 +547,13 @@ fn initialize_repository_information(general_context: &mut Context,
 {
 	let file_setup_start_time: Instant = Instant::now();
 
-	let mut feature_branch_folder_name: String = String::with_capacity(1 + FEATURE_BRANCH_TEMP_FOLDER.len());
+	let mut feature_branch_folder_name: String = String::with_capacity(80);
 	feature_branch_folder_name.push(slash());
-	feature_branch_folder_name.push_str(FEATURE_BRANCH_TEMP_FOLDER);
+	feature_branch_folder_name.push_str(&temp_folder_name(FEATURE_BRANCH_TEMP_FOLDER));
 
-	let mut compare_branch_folder_name = String::with_capacity(1 + COMPARE_BRANCH_TEMP_FOLDER.len());
+	let mut compare_branch_folder_name = String::with_capacity(80);
 	compare_branch_folder_name.push(slash());
-	compare_branch_folder_name.push_str(COMPARE_BRANCH_TEMP_FOLDER);
+	compare_branch_folder_name.push_str(&temp_folder_name(COMPARE_BRANCH_TEMP_FOLDER));
 
 	let mut feature_branch_path = String::from(join_paths([tool_context.working_path.clone(), 
 		feature_branch_folder_name.clone()])
@@ -253,7 +604,7 @@ fn initialize_repository_information(general_context: &mut Context,
 	];
 
 	let file_setup_time = file_setup_start_time.elapsed().as_secs_f64() * 1000.0;
-	let file_setup_time_message: String = String::from(format!("manifest::file setup: {}ms\n", file_setup_time));
+	let file_setup_time_message: String = format!("manifest::file setup: {}ms\n", file_setup_time);
 	tool_context.time_snapshots.push(file_setup_time_message);
 
 	return (repository_information, feature_branch_path, compare_branch_path);
@@ -263,52 +614,78 @@ fn manage_branches(tool_context: &mut ToolContext, repository_information: &[Rep
 {
 	let git_pulling_start_time: Instant = Instant::now();
 
-	let mut bitbucket_username: &String = &String::new();
-
-	if tool_context.configuration_variables.contains_key("bitbucket_username")
-	{
-		bitbucket_username = tool_context.configuration_variables.get_key_value("bitbucket_username").unwrap().1;
-	}
-	else
-	{
-		bitbucket_username = tool_context.command_parameters.get_key_value("bbuser").unwrap().1;
-	}
+	// Pulls run on a pool of their own, one thread per branch unless --concurrency
+	// says otherwise, rather than on rayon's global pool.
+	let thread_count: usize = tool_context.command_parameters.get("concurrency")
+		.and_then(|concurrency| concurrency.parse::<usize>().ok())
+		.unwrap_or(repository_information.len());
 
 	// TODO: Working path must be made to work with this parallel pulling action
 	// The problem is that tool_context.working_path, or reading from it across
 	// multiple threads, isn't safe, so this needs some additional thought
-	repository_information
-		.par_iter()
-		.for_each(
-			|repository_info| pull_branch_details(&mut tool_context.clone(), 
-				bitbucket_username.clone(), 
-				&repository_info));
+	let pull_branches = ||
+		repository_information
+			.par_iter()
+			.for_each(
+				|repository_info| pull_branch_details(&mut tool_context.clone(), repository_info));
+
+	match rayon::ThreadPoolBuilder::new().num_threads(thread_count).build()
+	{
+		Ok(thread_pool) => thread_pool.install(pull_branches),
+		Err(_) =>
+		{
+			// Without a pool of its own, pulling one branch after the other still works
+			for repository_info in repository_information
+			{ pull_branch_details(&mut tool_context.clone(), repository_info); }
+		}
+	}
 
 	let git_pulling_time: f64 = git_pulling_start_time.elapsed().as_secs_f64() * 1000.0;
-	let git_pulling_time_message: String = String::from(format!("manifest::git pulling: {}ms\n", git_pulling_time));
+	let git_pulling_time_message: String = format!("manifest::git pulling: {}ms\n", git_pulling_time);
 	tool_context.time_snapshots.push(git_pulling_time_message);
 }
 
-pub fn split_to_lines_vec(diffed_files_from_standard_out: &String) -> Vec<String>
+// The local Salesforce DX repository, read for its checked out branch and its
+// sfdx-project.json. This is the working path unless --repo-path points elsewhere,
+// while temp folders and output always stay in the working path.
+pub fn repository_path(tool_context: &ToolContext) -> &String
 {
-	let mut diff_files_by_lines: Vec<String> = Vec::with_capacity(64);
-	let mut current_value: String = String::with_capacity(128);
-	if diffed_files_from_standard_out.len() > 0
+	return match tool_context.command_parameters.get("repopath")
 	{
-		for character in diffed_files_from_standard_out.chars()
-		{
-			if character == '\n'
-			{
-				diff_files_by_lines.push(current_value.clone());
-				current_value = String::with_capacity(128);
-				continue;
-			}
+		Some(repository_path) => repository_path,
+		None => &tool_context.working_path,
+	};
+}
 
-			current_value.push(character);
-		}
-	}
+// A configuration variable counts as set when it has a value other than the
+// placeholder written into a fresh config.txt.
+fn variable_is_set(tool_context: &ToolContext, variable_name: &str) -> bool
+{
+	return match tool_context.configuration_variables.get(variable_name)
+	{
+		Some(value) => value.trim().len() > 0 && value != "[enter value]",
+		None => false,
+	};
+}
+
+// Reports the phase a run has reached, so slow network or git steps don't look
+// like the tool has frozen. Phase names follow the ones used in time snapshots, and
+// are recorded with every log message from then on.
+fn report_phase(general_context: &mut Context, tool_context: &ToolContext, phase: &str)
+{
+	general_context.logger.set_phase(phase);
+
+	if !tool_context.progress_on { return; }
+
+	eprint!("==> {}...\n", phase);
+}
 
-	return diff_files_by_lines;
+// Lines of diff output are borrowed from the output itself rather than copied into
+// a Vec, so a large diff is only ever held in memory once. Windows line endings
+// are handled, and a last line without a trailing new line is still returned.
+pub fn split_to_lines(diff_output: &str) -> std::str::Lines<'_>
+{
+	return diff_output.lines();
 }
 
 fn common_metadata_buckets(tool_context: &mut ToolContext) -> Vec<MetadataBucket>
@@ -323,7 +700,7 @@ fn common_metadata_buckets(tool_context: &mut ToolContext) -> Vec<MetadataBucket
 		MetadataBucket::new("compactLayouts", "CompactLayout", false),
 		MetadataBucket::new("customMetadata", "CustomMetadata", false),
 		MetadataBucket::new("customPermissions", "CustomPermission", false),
-		MetadataBucket::new("customSettings", "CustomSetting", false),
+		MetadataBucket::new("experiences", "ExperienceBundle", true),
 		MetadataBucket::new("externalCredentials", "ExternalCredential", false),
 		MetadataBucket::new("fieldSets", "FieldSet", false),
 		MetadataBucket::new("fields", "CustomField", false),
@@ -337,6 +714,7 @@ fn common_metadata_buckets(tool_context: &mut ToolContext) -> Vec<MetadataBucket
 		MetadataBucket::new("lwc", "LightningComponentBundle", true),
 		MetadataBucket::new("namedCredentials", "NamedCredential", false),
 		MetadataBucket::new("objects", "CustomObject", false),
+		MetadataBucket::new("objectTranslations", "CustomObjectTranslation", true),
 		MetadataBucket::new("pages", "ApexPage", false),
 		MetadataBucket::new("permissionsetgroups", "PermissionSetGroup", false),
 		MetadataBucket::new("permissionsets", "PermissionSet", false),
@@ -344,8 +722,10 @@ fn common_metadata_buckets(tool_context: &mut ToolContext) -> Vec<MetadataBucket
 		MetadataBucket::new("quickActions", "QuickAction", false),
 		MetadataBucket::new("recordTypes", "RecordType", false),
 		MetadataBucket::new("remoteSiteSettings", "RemoteSiteSetting", false),
-		MetadataBucket::new("searchLayouts", "SearchLayouts", false),
+		MetadataBucket::new("searchLayouts", "SearchLayout", false),
+		MetadataBucket::new("sharingReasons", "SharingReason", false),
 		MetadataBucket::new("standardValueSets", "StandardValueSet", false),
+		MetadataBucket::new("staticresources", "StaticResource", false),
 		MetadataBucket::new("tabs", "CustomTab", false),
 		MetadataBucket::new("triggers", "ApexTrigger", false),
 		MetadataBucket::new("validationRules", "ValidationRule", false),
@@ -353,7 +733,7 @@ fn common_metadata_buckets(tool_context: &mut ToolContext) -> Vec<MetadataBucket
 	];
 
 	let metadata_bucket_time: f64 = metadata_bucket_time_start.elapsed().as_secs_f64() * 1000.0;
-	let metadata_bucket_time_message: String = String::from(format!("manifest::metadata buckets initialization: {}ms\n", metadata_bucket_time));
+	let metadata_bucket_time_message: String = format!("manifest::metadata buckets initialization: {}ms\n", metadata_bucket_time);
 	tool_context.time_snapshots.push(metadata_bucket_time_message);
 
 	return metadata_buckets;
@@ -364,36 +744,147 @@ fn map_metadata_buckets(metadata_buckets: &Vec<MetadataBucket>) -> HashMap<Strin
 
 	let mut bucket_folder_name_to_index: HashMap<String, usize> = HashMap::with_capacity(32);
 
-	let mut bucket_index: usize = 0;
-	for metadata_bucket in metadata_buckets
+	for (bucket_index, metadata_bucket) in metadata_buckets.iter().enumerate()
 	{
 		bucket_folder_name_to_index.insert(metadata_bucket.file_path_name.clone(), bucket_index);
-		bucket_index += 1;
 	}
 
 	return bucket_folder_name_to_index;
 }
 
-fn change_code_constructive(change_code: &String) -> bool
+// The folder metadata category folders (classes, objects, ...) live under, with a
+// trailing slash. Diff paths always use forward slashes, so a root given with
+// backslashes or a leading ./ is normalized to match.
+fn metadata_root(tool_context: &ToolContext) -> String
+{
+	let metadata_root: &str = match tool_context.command_parameters.get("metadataroot")
+	{
+		Some(metadata_root) => metadata_root,
+		None => DEFAULT_METADATA_ROOT,
+	};
+
+	let mut normalized_root: String = metadata_root.replace('\\', "/");
+	while let Some(stripped_root) = normalized_root.strip_prefix("./")
+	{ normalized_root = stripped_root.to_string(); }
+
+	normalized_root = normalized_root.trim_matches('/').to_string();
+	normalized_root.push('/');
+	return normalized_root;
+}
+
+// The change statuses (first letter of a git --name-status code) whose path is a
+// removal for destructiveChanges.xml: D and R by default, or the comma separated
+// list given with --destructive-statuses.
+fn destructive_statuses(tool_context: &ToolContext) -> Vec<char>
+{
+	let configured_statuses: &str = match tool_context.command_parameters.get("destructivestatuses")
+	{
+		Some(configured_statuses) => configured_statuses,
+		None => "D,R",
+	};
+
+	return configured_statuses
+		.split(',')
+		.filter_map(|status| status.trim().chars().next())
+		.map(|status| status.to_ascii_uppercase())
+		.collect();
+}
+
+// The regular expression given with --include-pattern or --exclude-pattern. Both
+// were already checked to compile when the options were parsed.
+fn member_pattern(tool_context: &ToolContext, parameter_key: &str) -> Option<Regex>
+{
+	return tool_context.command_parameters.get(parameter_key)
+		.and_then(|pattern| Regex::new(pattern).ok());
+}
+
+// Inserts a member into its bucket and, with --verbose, logs the file it came from
+// (`Type: Member <= path`), so a member nobody expected can be traced back to the
+// change that produced it. Bundles log once per file that collapses into them.
+// A path the parser couldn't get a member name out of is skipped rather than
+// listed as an empty <members/>, and false is returned.
+fn insert_traced_member(general_context: &mut Context,
+	verbose: bool,
+	bucket: &mut MetadataBucket,
+	member_name: String,
+	change_kind: ChangeKind,
+	source: &str) -> bool
 {
-	if change_code.starts_with('D') || change_code.starts_with('R')
+	if member_name.trim().len() == 0
 	{
+		general_context.logger.log_warning(&format!("WARNING: No {} member name could be parsed from {}, so it has been skipped.\n", bucket.package_xml_name, source));
 		return false;
 	}
 
+	if verbose
+	{
+		let destructive_note: &str = if change_kind == ChangeKind::Destructive { " (destructive)" } else { "" };
+		general_context.logger.log_verbose(&format!("{}: {} <= {}{}\n", bucket.package_xml_name, member_name, source, destructive_note));
+	}
+
+	bucket.insert_member(member_name, change_kind);
 	return true;
 }
 
-// Most metadata categories are individual files within the standard folder name, and
-// can be copied that way straight up, so this will be the most commonly used function
-// for parsing the file path into its corresponding manifest text.
-fn basic_name(change_code: &String, name_minus_root: &String, current_metadata_bucket: &mut MetadataBucket)
+// Inserts what a name parser made of a path, or reports the path as skipped when
+// the parser couldn't handle it.
+fn insert_parsed_member(general_context: &mut Context,
+	verbose: bool,
+	bucket: &mut MetadataBucket,
+	parsed_member: Option<(String, ChangeKind)>,
+	source: &str) -> bool
+{
+	match parsed_member
+	{
+		Some((member_name, change_kind)) => return insert_traced_member(general_context, verbose, bucket, member_name, change_kind, source),
+		None =>
+		{
+			general_context.logger.log_warning(&format!("WARNING: No {} member name could be parsed from {}, so it has been skipped.\n", bucket.package_xml_name, source));
+			return false;
+		}
+	}
+}
+
+// The result of a name parser, which is None when no member name could be found
+// in the path, e.g. a file sitting directly in the category folder's parent.
+fn non_empty_member(member_name: String, change_kind: ChangeKind) -> Option<(String, ChangeKind)>
+{
+	if member_name.trim().len() == 0
+	{ return None; }
+
+	return Some((member_name, change_kind));
+}
+
+// Whether a parsed member belongs in package.xml (constructive) or in
+// destructiveChanges.xml (destructive).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind
+{
+	Constructive,
+	Destructive,
+}
+
+impl ChangeKind
+{
+	// Change codes are normalized before parsing, so only D is ever destructive here;
+	// whether a rename counts is settled by --destructive-statuses beforehand.
+	fn from_change_code(change_code: &String) -> ChangeKind
+	{
+		if change_code.starts_with('D') { return ChangeKind::Destructive; }
+
+		return ChangeKind::Constructive;
+	}
+}
+
+// Takes the file name following the category folder, minus everything from the
+// first '.' onward, e.g. classes/MyClass.cls-meta.xml becomes MyClass.
+fn file_name_without_extension(name_minus_root: &String) -> String
 {
 	let mut revised_name_stripped_of_file_extension: String = String::with_capacity(80);
 	let mut reading: bool = false; // Doesn't matter until we hit first slash
 	'revised_name: for name_char in name_minus_root.chars()
 	{
-		if name_char == '/' || name_char == '\\' { reading = true; continue 'revised_name; }
+		if PATH_SEPARATORS.contains(&name_char) { reading = true; continue 'revised_name; }
 
 		if !reading { continue; }
 
@@ -402,33 +893,82 @@ fn basic_name(change_code: &String, name_minus_root: &String, current_metadata_b
 		revised_name_stripped_of_file_extension.push(name_char);
 	}
 
-	if change_code_constructive(change_code)
+	return revised_name_stripped_of_file_extension;
+}
+
+// Most metadata categories are individual files within the standard folder name, and
+// can be copied that way straight up, so this will be the most commonly used function
+// for parsing the file path into its corresponding manifest text.
+fn basic_name(change_code: &String, name_minus_root: &String) -> Option<(String, ChangeKind)>
+{
+	return non_empty_member(file_name_without_extension(name_minus_root), ChangeKind::from_change_code(change_code));
+}
+
+// Standard value sets are the picklist values behind Salesforce's own fields
+// (standardValueSets/LeadSource.standardValueSet-meta.xml -> LeadSource), so the
+// member is a Salesforce-defined name rather than one chosen by the org. Those
+// names never carry a custom suffix or namespace, which belong to global value sets
+// instead, and the sets themselves can't be deleted, only emptied, so either case
+// is returned as an error describing why the file was left out.
+fn standard_value_set_name(change_code: &String, name_minus_root: &String) -> Result<String, String>
+{
+	let value_set_name = file_name_without_extension(name_minus_root);
+
+	if value_set_name.contains("__")
+	{
+		return Err(format!("{} is not a Salesforce-defined standard value set, custom value sets belong in globalValueSets", value_set_name));
+	}
+
+	if ChangeKind::from_change_code(change_code) == ChangeKind::Destructive
 	{
-		current_metadata_bucket.files.insert(
-			revised_name_stripped_of_file_extension
-		);
+		return Err(format!("standard value set {} can't be deleted, remove its values instead", value_set_name));
 	}
-	else
+
+	return Ok(value_set_name);
+}
+
+// Flows deploy by their API name (flows/MyFlow.flow-meta.xml -> MyFlow), but some
+// source trees hold versioned files such as MyFlow-3.flow-meta.xml. Flow API names
+// cannot contain '-', so a trailing -<number> is always a version, and it is dropped
+// so the member is the bare API name the Metadata API expects. With
+// --keep-flow-versions the version suffix is left in place instead.
+fn flow_name(change_code: &String,
+	name_minus_root: &String,
+	keep_version: bool) -> Option<(String, ChangeKind)>
+{
+	let mut flow_api_name = file_name_without_extension(name_minus_root);
+
+	if !keep_version
+		&& let Some((api_name, version)) = flow_api_name.rsplit_once('-')
+		&& version.len() > 0
+		&& version.chars().all(|character| character.is_ascii_digit())
 	{
-		current_metadata_bucket.destructive_files.insert(
-			revised_name_stripped_of_file_extension
-		);
+		flow_api_name = api_name.to_string();
 	}
-	
+
+	return non_empty_member(flow_api_name, ChangeKind::from_change_code(change_code));
 }
 
 // The bundle consists of usually between 3 to 5 files or so inside of a folder,
 // and the only thing we actually want for the package.xml manifest is the folder
 // name, as that's all that's included - there's no specifying the individual HTML,
 // .js or .css files included within the bundle.
-fn bundle_name(name_minus_root: &String, current_metadata_bucket: &mut MetadataBucket)
+//
+// Some bundles (Experience Cloud sites) also keep their definition file beside the
+// folder rather than inside it, e.g. experiences/MySite1.site-meta.xml, so anything
+// from a '.' onward in the bundle name is dropped as well.
+//
+// Deleting a single file within a bundle just means the bundle is redeployed without
+// it. Only when the bundle's own -meta.xml definition is deleted is the whole bundle
+// going away, so that's what lands the bundle in destructive output.
+fn bundle_name(change_code: &String, name_minus_root: &String) -> Option<(String, ChangeKind)>
 {
 	let mut revised_name: String = String::with_capacity(80);
 	let mut found_first_slash = false;
 
 	for character in name_minus_root.chars()
 	{
-		let is_a_slash: bool = character == '/' || character == '\\';
+		let is_a_slash: bool = PATH_SEPARATORS.contains(&character);
 
 		if !found_first_slash && !is_a_slash { continue; }
 
@@ -436,16 +976,91 @@ fn bundle_name(name_minus_root: &String, current_metadata_bucket: &mut MetadataB
 
 		if is_a_slash && found_first_slash { break; }
 
+		if found_first_slash && character == '.' { break; }
+
 		if found_first_slash
 		{
 			revised_name.push(character);
 		}
 	}
 
-	current_metadata_bucket.files.insert(revised_name);
+	let bundle_definition_deleted = ChangeKind::from_change_code(change_code) == ChangeKind::Destructive && name_minus_root.ends_with("-meta.xml");
+
+	if bundle_definition_deleted
+	{
+		return non_empty_member(revised_name, ChangeKind::Destructive);
+	}
+
+	return non_empty_member(revised_name, ChangeKind::Constructive);
+}
+
+// Static resources come in two forms, and the definition file sits directly in the
+// staticresources folder either way:
+//
+//	staticresources/MyLib.resource + MyLib.resource-meta.xml   (a single file)
+//	staticresources/MyLib/js/lib.js + MyLib.resource-meta.xml  (an unzipped folder)
+//
+// A path with a further folder is the unzipped form, whose member is that folder's
+// name. Otherwise the member is the file name up to its extension. As with bundles,
+// only deleting the definition takes the resource away; any other deleted file just
+// means it is redeployed without it.
+fn static_resource_name(change_code: &String, name_minus_root: &String) -> Option<(String, ChangeKind)>
+{
+	let resource_path: &str = match name_minus_root.split_once(PATH_SEPARATORS)
+	{
+		Some((_category, resource_path)) => resource_path,
+		None => name_minus_root,
+	};
+
+	let resource_name: &str = match resource_path.split_once(PATH_SEPARATORS)
+	{
+		Some((folder_name, _file_path)) => folder_name,
+		None => resource_path.split('.').next().unwrap_or_default(),
+	};
+
+	let resource_definition_deleted = ChangeKind::from_change_code(change_code) == ChangeKind::Destructive && resource_path.ends_with(".resource-meta.xml");
+
+	if resource_definition_deleted
+	{
+		return non_empty_member(resource_name.to_string(), ChangeKind::Destructive);
+	}
+
+	return non_empty_member(resource_name.to_string(), ChangeKind::Constructive);
+}
+
+// Object translations are a folder per object and language, e.g.
+// objectTranslations/Account-es/, holding the object's translation file
+// (Account-es.objectTranslation-meta.xml) alongside field translations. The member
+// is always the folder name. This is kept apart from object_metadata since the
+// folder is not an object, and apart from bundle_name since a deleted field
+// translation only means the folder is redeployed; only deleting the
+// .objectTranslation-meta.xml file removes the translation itself.
+fn object_translation_name(change_code: &String, name_minus_root: &String) -> Option<(String, ChangeKind)>
+{
+	let path_segments: Vec<&str> = name_minus_root
+		.split(PATH_SEPARATORS)
+		.filter(|segment| segment.len() > 0)
+		.collect();
+
+	if path_segments.len() < 3
+	{ return None; }
+
+	let translation_name: String = path_segments[1].to_string();
+	let translation_file_prefix: String = format!("{}.objectTranslation", translation_name);
+	let translation_deleted = ChangeKind::from_change_code(change_code) == ChangeKind::Destructive
+		&& path_segments.len() == 3
+		&& path_segments[2].starts_with(&translation_file_prefix);
+
+	if translation_deleted
+	{
+		return Some((translation_name, ChangeKind::Destructive));
+	}
+
+	return Some((translation_name, ChangeKind::Constructive));
 }
 
-fn quick_action_name(change_code: &String, name_minus_root: &String, current_metadata_bucket: &mut MetadataBucket)
+// Returns None when the path doesn't end in the quick action file extension.
+fn quick_action_name(change_code: &String, name_minus_root: &String) -> Option<(String, ChangeKind)>
 {
 	let mut revised_name: String = String::with_capacity(80);
 	let mut found_first_slash = false;
@@ -459,7 +1074,7 @@ fn quick_action_name(change_code: &String, name_minus_root: &String, current_met
 	{
 		current_position += 1;
 		
-		let is_a_slash = character == '/' || character == '\\';
+		let is_a_slash = PATH_SEPARATORS.contains(&character);
 		
 		if !found_first_slash && !is_a_slash { continue; }
 
@@ -469,16 +1084,7 @@ fn quick_action_name(change_code: &String, name_minus_root: &String, current_met
 
 		if number_remaining == extension_length
 		{
-			if change_code_constructive(change_code)
-			{
-				current_metadata_bucket.files.insert(revised_name);
-			}
-			else
-			{
-				current_metadata_bucket.destructive_files.insert(revised_name);
-			}
-			
-			break;
+			return Some((revised_name, ChangeKind::from_change_code(change_code)));
 		}
 
 		if found_first_slash
@@ -486,158 +1092,275 @@ fn quick_action_name(change_code: &String, name_minus_root: &String, current_met
 			revised_name.push(character);
 		}		
 	}
+
+	return None;
 }
 
-fn object_metadata(change_code: &String,
-	name_minus_root: &String,
-	metadata_category_map: &HashMap<String, usize>,
-	all_metadata_buckets: &mut Vec<MetadataBucket>)
+// Custom objects are a folder rather than a file, and everything about an object
+// lives underneath it. The path (after the source root is removed) has one of
+// two shapes:
+//
+// objects/Account/Account.object-meta.xml            -> CustomObject: Account
+// objects/Account/fields/Primary_Contact__c.field-meta.xml -> CustomField: Account.Primary_Contact__c
+//
+// The first describes the object itself, and the member is the object folder's
+// name. The second describes a child of the object (fields, validation rules,
+// record types, and so on), where the sub-folder selects the metadata bucket and
+// the member is formatted as Object.Name, such as:
+// Account.AnnualRevenue
+// Account.Primary_Contact__c
+// Opportunity.CES_Contract__c
+// App_Log__c.Message__c
+//
+// Returns the key of the metadata bucket the member belongs in along with the
+// member, or None if the path doesn't fit either shape or names a sub-folder that
+// isn't an object child category, so the caller can report it rather than letting
+// the change silently disappear from the manifest.
+//
+// A deleted object comes through as a D on its own meta file and on each of its
+// child files, and every one of them is listed as destructive. Salesforce removes an
+// object's fields along with the object, so the child entries aren't required, but
+// listing them keeps destructiveChanges.xml matching the diff.
+fn object_metadata(change_code: &String, name_minus_root: &String) -> Option<(String, String, ChangeKind)>
 {
-	let mut object_name: String = String::with_capacity(80);
-	let mut category_name: String = String::with_capacity(80);
-	let mut file_name: String = String::with_capacity(80);
+	let path_segments: Vec<&str> = name_minus_root
+		.split(PATH_SEPARATORS)
+		.filter(|segment| segment.len() > 0)
+		.collect();
 
-	let mut writing_object_name: bool = false;
-	let mut writing_category_name: bool = false;
-	let mut writing_file_name: bool = false;
+	let (bucket_key, member_name) = match path_segments.as_slice()
+	{
+		[_objects_folder, object_name, object_file_name] =>
+		{
+			// Only the object's own meta file sits directly in the object folder
+			if !object_file_name.starts_with(&format!("{}.", object_name))
+			{ return None; }
 
-	for character in name_minus_root.chars()
+			("objects", object_name.to_string())
+		},
+		[_objects_folder, object_name, category_name, child_file_name] =>
+		{
+			if !OBJECT_CHILD_CATEGORIES.contains(category_name)
+			{ return None; }
+
+			let child_name = child_file_name.split('.').next().unwrap_or_default();
+
+			if child_name.len() == 0
+			{ return None; }
+
+			(*category_name, format!("{}.{}", object_name, child_name))
+		},
+		_ => return None,
+	};
+
+	return Some((bucket_key.to_string(), member_name, ChangeKind::from_change_code(change_code)));
+}
+
+// Custom metadata records are named Type.Record after their file, minus the
+// customMetadata folder and the .md-meta.xml extension. A deleted record file
+// removes the record itself, so it goes to destructiveChanges.xml.
+fn custom_metadata_name(change_code: &String, name_minus_root: &String) -> Option<(String, ChangeKind)>
+{
+	let file_name: &str = match name_minus_root.split_once(PATH_SEPARATORS)
 	{
-		let is_a_slash = character == '/' || character == '\\';
+		Some((_category, file_name)) => file_name,
+		None => name_minus_root,
+	};
 
-		if is_a_slash && !writing_object_name && !writing_category_name && !writing_file_name
-		{ writing_object_name = true; continue; }
+	return non_empty_member(file_name.strip_suffix(".md-meta.xml").unwrap_or(file_name).to_string(), ChangeKind::from_change_code(change_code));
+}
 
-		if is_a_slash && !writing_category_name
-		{
-			writing_object_name = false;
-			writing_category_name = true;
-			
-			continue;
-		}
+// The single level of indentation used in the generated XML, as chosen
+// with --indent. Nested elements repeat this unit.
+fn indent_unit(tool_context: &ToolContext) -> String
+{
+	return match tool_context.command_parameters.get("indent").map(|indent| indent.as_str())
+	{
+		Some("2") => String::from("  "),
+		Some("4") => String::from("    "),
+		_ => String::from("\t"),
+	};
+}
 
-		if is_a_slash && !writing_file_name
+// Escapes the characters that are not allowed to appear literally inside XML
+// element content. Metadata API names are normally safe, but folder-based
+// metadata and labels can contain characters such as '&'.
+fn xml_escape(value: &str) -> String
+{
+	let mut escaped: String = String::with_capacity(value.len() + 8);
+	for character in value.chars()
+	{
+		match character
 		{
-			writing_category_name = false;
-			writing_file_name = true;			
-			continue;
+			'&' => escaped.push_str("&amp;"),
+			'<' => escaped.push_str("&lt;"),
+			'>' => escaped.push_str("&gt;"),
+			'"' => escaped.push_str("&quot;"),
+			'\'' => escaped.push_str("&apos;"),
+			_ => escaped.push(character),
 		}
+	}
 
-		// If hitting a . and not yet writing the filename, that means
-		// that, actually, the category name is really the filename, and
-		// this is describing the custom object itself.
-		if character == '.' && !writing_file_name
-		{
-			let custom_object_bucket_index = metadata_category_map.get_key_value("objects").unwrap().1;
-			let object_bucket = &mut all_metadata_buckets[*custom_object_bucket_index];
+	return escaped;
+}
 
-			if change_code_constructive(change_code)
-			{
-				object_bucket.files.insert(category_name.clone());
-			}
-			else
-			{
-				object_bucket.destructive_files.insert(category_name.clone());
-			}
-			break;
-		}
+// A minimal well-formedness check for the manifests this tool builds: every
+// opening tag must be closed in order, there must be a single root element and
+// every '&' must begin a recognized entity reference. This is not a general
+// purpose XML parser, only a guard against writing a document sf would reject.
+fn validate_xml_document(document: &String) -> Result<(), String>
+{
+	let mut open_elements: Vec<String> = Vec::with_capacity(8);
+	let mut root_elements: usize = 0;
+	let mut remaining: &str = document.as_str();
 
-		// If reaching the ., this is probably the file extension
-		// for the .field filename, so bail out here, as this should not
-		// make its way onto the final manifest.
-		if character == '.' && writing_file_name
-		{
+	while let Some(special_index) = remaining.find(['<', '&'])
+	{
+		remaining = &remaining[special_index..];
 
-			if !metadata_category_map.contains_key(&category_name)
+		if remaining.starts_with('&')
+		{
+			let entity_end = match remaining.find(';')
 			{
-				// TODO: This should really be some kind of error, but not
-				// sure how to handle it just yet, so just break for now,
-				// but we probably need to use the logger to record this and
-				// display an error in the terminal
-				break;
-			}
+				Some(entity_end) => entity_end,
+				None => return Err(String::from("unterminated entity reference")),
+			};
 
-			let custom_field_bucket_index = metadata_category_map.get_key_value(&category_name).unwrap().1;
-			let fields_bucket = &mut all_metadata_buckets[*custom_field_bucket_index];
+			let entity = &remaining[1..entity_end];
+			let known_entity = matches!(entity, "amp" | "lt" | "gt" | "quot" | "apos")
+				|| (entity.starts_with("#x") && entity.len() > 2 && entity[2..].chars().all(|character| character.is_ascii_hexdigit()))
+				|| (entity.starts_with('#') && entity.len() > 1 && entity[1..].chars().all(|character| character.is_ascii_digit()));
 
-			if change_code_constructive(change_code)
-			{
-				fields_bucket.files.insert(file_name);
-			}
-			else
-			{
-				fields_bucket.destructive_files.insert(file_name);
-			}
+			if !known_entity
+			{ return Err(format!("invalid entity reference &{};", entity)); }
 
-			break;
+			remaining = &remaining[entity_end + 1..];
+			continue;
 		}
 
-		if writing_object_name { object_name.push(character); }
-		if writing_category_name { category_name.push(character); }
-		if writing_file_name
+		let tag_end = match remaining.find('>')
+		{
+			Some(tag_end) => tag_end,
+			None => return Err(String::from("unterminated tag")),
+		};
+
+		let tag = &remaining[1..tag_end];
+		remaining = &remaining[tag_end + 1..];
+
+		if tag.starts_with('?') { continue; } // XML declaration
+
+		if let Some(closing_name) = tag.strip_prefix('/')
 		{
-			// Fields are formatted as having the object API name,
-			// followed by the field API name, such as the following
-			// examples below:
-			// Account.AnnualRevenue
-			// Account.Primary_Contact__c
-			// Opportunity.CES_Contract__c
-			// App_Log__c.Message__c
-			// and so on.
-			if file_name.len() == 0
+			match open_elements.pop()
 			{
-				file_name.push_str(&object_name);
-				file_name.push('.');
+				Some(opening_name) if opening_name == closing_name.trim() => {},
+				Some(opening_name) => return Err(format!("</{}> does not close <{}>", closing_name.trim(), opening_name)),
+				None => return Err(format!("</{}> has no matching opening tag", closing_name.trim())),
 			}
-
-			file_name.push(character);
+			continue;
 		}
+
+		let element_name: String = tag.split_whitespace().next().unwrap_or_default().trim_end_matches('/').to_string();
+		if element_name.len() == 0 || tag.contains('<')
+		{ return Err(format!("malformed tag <{}>", tag)); }
+
+		if open_elements.len() == 0
+		{ root_elements += 1; }
+
+		if !tag.ends_with('/')
+		{ open_elements.push(element_name); }
 	}
 
+	if let Some(unclosed_name) = open_elements.pop()
+	{ return Err(format!("<{}> is never closed", unclosed_name)); }
+
+	if root_elements != 1
+	{ return Err(format!("expected a single root element but found {}", root_elements)); }
+
+	return Ok(());
 }
 
-fn custom_metadata_name(name_minus_root: &String, 
-	current_metadata_bucket: &mut MetadataBucket)
+// Compares two member names so that embedded runs of digits are ordered by
+// their numeric value, e.g. Field_2 sorts before Field_10. Everything else is
+// compared character by character as in a plain lexicographic sort.
+fn natural_compare(left: &String, right: &String) -> Ordering
 {
-	// Uses the length of the custom metadata file extension to know 
-	// when to bail out of parsing the string. In this case, it is
-	// the 11 characters from:
-	// .md-meta.xml
-	let custom_metadata_file_ext_len: usize = 12;
+	let mut left_characters = left.chars().peekable();
+	let mut right_characters = right.chars().peekable();
 
-	let mut custom_metadata_name: String = String::with_capacity(80);
-	let mut current_character_index: usize = 0;
-	let mut past_first_slash: bool = false; // Skipping past the 'customMetadata/' filename prefix
-	let length_of_prefix: usize = 15;
-	for character in name_minus_root.chars()
+	loop
 	{
-		if character == '/' || character == '\\' && !past_first_slash 
-		{ past_first_slash = true; continue; }
+		let (left_character, right_character) = match (left_characters.peek(), right_characters.peek())
+		{
+			(None, None) => return Ordering::Equal,
+			(None, Some(_)) => return Ordering::Less,
+			(Some(_), None) => return Ordering::Greater,
+			(Some(left_character), Some(right_character)) => (*left_character, *right_character),
+		};
+
+		if left_character.is_ascii_digit() && right_character.is_ascii_digit()
+		{
+			let mut left_number: String = String::with_capacity(8);
+			while let Some(digit) = left_characters.next_if(|character| character.is_ascii_digit())
+			{ left_number.push(digit); }
+
+			let mut right_number: String = String::with_capacity(8);
+			while let Some(digit) = right_characters.next_if(|character| character.is_ascii_digit())
+			{ right_number.push(digit); }
 
-		if !past_first_slash { continue; }
+			// Comparing the digit strings without leading zeros, first by length and
+			// then lexicographically, avoids overflow on arbitrarily long numbers.
+			let left_trimmed = left_number.trim_start_matches('0');
+			let right_trimmed = right_number.trim_start_matches('0');
+
+			let ordering = left_trimmed.len().cmp(&right_trimmed.len())
+				.then_with(|| left_trimmed.cmp(right_trimmed))
+				.then_with(|| left_number.len().cmp(&right_number.len()));
+
+			if ordering != Ordering::Equal { return ordering; }
+			continue;
+		}
 
-		custom_metadata_name.push(character);
+		if left_character != right_character
+		{ return left_character.cmp(&right_character); }
 
-		current_character_index += 1;
-		if name_minus_root.len() - length_of_prefix - current_character_index == custom_metadata_file_ext_len { break; }
+		left_characters.next();
+		right_characters.next();
 	}
+}
 
-	current_metadata_bucket.files.insert(custom_metadata_name);
+// Splits an API version such as 62.0 into its numeric parts for comparison, with
+// anything unparseable sorting lowest.
+fn api_version_parts(api_version: &str) -> (u32, u32)
+{
+	let (major_version, minor_version) = api_version.split_once('.').unwrap_or((api_version, "0"));
+	return (major_version.parse::<u32>().unwrap_or(0), minor_version.parse::<u32>().unwrap_or(0));
 }
 
-fn sort_metadata_buckets(general_context: &mut Context,
-	tool_context: &mut ToolContext,
-	diffed_files_by_lines: &Vec<String>) -> ManifestBundle
+// The <version> written into both manifests: the project's sourceApiVersion when
+// sfdx-project.json has one, otherwise the default, raised to --min-api-version.
+fn manifest_api_version(general_context: &mut Context, tool_context: &ToolContext) -> String
 {
-	if diffed_files_by_lines.len() >= MAXIMUM_DIFF_FILE_SIZE
+	let detected_api_version: String = source_api_version(repository_path(tool_context))
+		.filter(|api_version| api_version.len() > 0)
+		.unwrap_or_else(|| DEFAULT_API_VERSION.to_string());
+
+	return match tool_context.command_parameters.get("minapiversion")
 	{
-		general_context.logger.log_error(
-			&format!("ERROR: Number of files in diff exceeds the maximum file size of {}, exiting...\n", MAXIMUM_DIFF_FILE_SIZE)
-		);
+		Some(min_api_version) if api_version_parts(&detected_api_version) < api_version_parts(min_api_version) =>
+		{
+			general_context.logger.log_info(&format!("API version {} is below --min-api-version, so {} is used instead.\n", detected_api_version, min_api_version));
+			min_api_version.clone()
+		},
+		_ => detected_api_version,
+	};
+}
 
-		return ManifestBundle::new();
-	}
-	
+fn sort_metadata_buckets<'a>(general_context: &mut Context,
+	tool_context: &mut ToolContext,
+	diffed_files_by_lines: impl Iterator<Item = &'a str>,
+	source_path: Option<&String>) -> ManifestBundle
+{
 	// Each metadata bucket contains handling information for how the category
 	// should be organized. The first step is to put all files into their respective
 	// metadata buckets, with the .files property on each bucket indicating what should
@@ -648,9 +1371,48 @@ fn sort_metadata_buckets(general_context: &mut Context,
 	general_context.logger.log_info(&format!("all_metadata_buckets.len(): {}\n", all_metadata_buckets.len()));
 	let metadata_category_map = map_metadata_buckets(&all_metadata_buckets);
 
-	let standard_folder = "force-app/main/default/";
-	for line in diffed_files_by_lines
+	if tool_context.command_parameters.contains_key("dedupecase")
+	{
+		for bucket in &mut all_metadata_buckets
+		{ bucket.case_insensitive = true; }
+	}
+
+	let keep_flow_versions: bool = tool_context.command_parameters.contains_key("keepflowversions");
+	let include_parent_objects: bool = tool_context.command_parameters.contains_key("includeparentobjects");
+	let verbose: bool = tool_context.command_parameters.contains_key("verbose");
+	let destructive_statuses: Vec<char> = destructive_statuses(tool_context);
+
+	let standard_folder: String = metadata_root(tool_context);
+	// Changed files outside the metadata root are skipped, which is expected for a
+	// few files (README.md, sfdx-project.json) but not for every one of them.
+	let mut changed_line_count: usize = 0;
+	let mut out_of_scope_line_count: usize = 0;
+	let mut parse_failure_count: usize = 0;
+	let mut unsupported_categories: BTreeSet<String> = BTreeSet::new();
+	let mut changed_files: BTreeSet<String> = BTreeSet::new();
+
+	for (line_index, line) in diffed_files_by_lines.enumerate()
 	{
+		// Lines are streamed, so the size limit is enforced as soon as it is reached
+		if line_index + 1 >= MAXIMUM_DIFF_FILE_SIZE
+		{
+			general_context.logger.log_error(
+				&format!("ERROR: Number of files in diff exceeds the maximum file size of {}, exiting...\n", MAXIMUM_DIFF_FILE_SIZE)
+			);
+
+			tool_context.exit_code = 1;
+			return ManifestBundle::new();
+		}
+
+		if line.len() > MAXIMUM_DIFF_LINE_LENGTH
+		{
+			general_context.logger.log_warning(
+				&format!("WARNING: Diff line {} is {} characters long, over the limit of {}, and has been skipped.\n", line_index + 1, line.len(), MAXIMUM_DIFF_LINE_LENGTH)
+			);
+
+			continue;
+		}
+
 		// This scan needs to take place in order to capture what the current change code is.
 		// The change code in this definition is stuff like `M` for modified, `D` for deleted,
 		// or R072 / R073 / R080 for renames. Renames are actually treated as both inserts and
@@ -708,181 +1470,436 @@ fn sort_metadata_buckets(general_context: &mut Context,
 			{ line_renamed_file_path.push(character); continue; }
 		}
 
-		print!("change_code: {}, line_file_path: {}\n", change_code, line_file_path);
+		general_context.logger.log_info(&format!("change_code: {}, line_file_path: {}\n", change_code, line_file_path));
+
+		// The metadata root is normalized to forward slashes, so a backslash separated
+		// path has to be as well or it would never be found under the root.
+		if line_file_path.contains('\\') { line_file_path = line_file_path.replace('\\', "/"); }
+
+		// Each changed path is parsed with a plain change code: D when its status is
+		// one of the destructive statuses (D and R unless --destructive-statuses says
+		// otherwise), and the line's own code when it isn't. A removal whose status
+		// isn't destructive (a rename treated as a plain move, say) is left out of
+		// both manifests.
+		let change_status: char = change_code.chars().next().unwrap_or_default();
+		let mut path_changes: Vec<(String, String)> = Vec::with_capacity(1);
+
+		if line_file_path.len() > 0
+		{
+			if destructive_statuses.contains(&change_status)
+			{ path_changes.push((String::from("D"), line_file_path.clone())); }
+			else if change_status != 'D' && change_status != 'R'
+			{ path_changes.push((change_code.clone(), line_file_path.clone())); }
+			else if verbose
+			{ general_context.logger.log_verbose(&format!("{} skipped, {} is not a destructive status\n", line_file_path, change_status)); }
+		}
 
-		// If the line does not start with force-app/main/default, this means it's packaged,
-		// as there's a preceding directory to the force-app file structure. Unpackaged metadata
-		// is the default and historically rampant.
-		if line_file_path.starts_with("force-app")
+		for (change_code, line_file_path) in path_changes
 		{
-			let name_minus_root = line_file_path.replace(standard_folder, "");
-			print!("{}\n", name_minus_root);
+			changed_line_count += 1;
 
-			// Parse the root phrase of the name_minus_root variable, 
-			// as this determines which metadata bucket should be utilized.
-			let mut root_metadata_category: String = String::with_capacity(80);
+			// If the line does not start with force-app/main/default (or the --metadata-root
+			// given), this means it's packaged, as there's a preceding directory to the
+			// force-app file structure. Unpackaged metadata is the default and historically rampant.
+			// Metadata-format unpackaged/ folders from older retrieves are read the same way.
+			let name_minus_root: Option<&str> = line_file_path.strip_prefix(standard_folder.as_str())
+				.or_else(|| line_file_path.strip_prefix(METADATA_FORMAT_ROOT));
 
-			let scan_mode_root_category: u8 = 0;
-			let scan_mode_read_category: u8 = 1;
-			let mut current_mode = scan_mode_root_category;
+			// A parser that can't handle one odd path reports it and the path is skipped,
+			// so the rest of the diff still makes it into the manifest.
+			let mut parsed: bool = true;
 
-			// Initializing with the first bucket here just to have a non-null reference
-			// This is changed once a supported metadata category is found because it will
-			// drop that reference in this slot to add it into the bucket's 'files' Vec.
-			for character in name_minus_root.chars()
+			if let Some(name_minus_root) = name_minus_root
 			{
-				let found_slash = character == '/' || character == '\\';
-
-				// If reaching the first slash, this indicates that the mode
-				// has changed from reading the root_metadata_category, to 
-				// then dealing with what lay out on the rest of the file
-				// path.
-				if found_slash && current_mode == scan_mode_root_category
+				changed_files.insert(line_file_path.clone());
+				let name_minus_root: String = name_minus_root.to_string();
+				general_context.logger.log_info(&format!("{}\n", name_minus_root));
+
+				// Parse the root phrase of the name_minus_root variable, 
+				// as this determines which metadata bucket should be utilized.
+				let mut root_metadata_category: String = String::with_capacity(80);
+
+				let scan_mode_root_category: u8 = 0;
+				let scan_mode_read_category: u8 = 1;
+				let mut current_mode = scan_mode_root_category;
+
+				// Initializing with the first bucket here just to have a non-null reference
+				// This is changed once a supported metadata category is found because it will
+				// drop that reference in this slot to add it into the bucket's 'files' Vec.
+				for character in name_minus_root.chars()
 				{
-					// Shift mode to handling a given category
-					current_mode = scan_mode_read_category;
-
-					// If handling a category, determine what bucket it corresponds to,
-					// if any. If it doesn't, then we display an error that there is 
-					// an unsupported metadata category
-					let support_metadata_category = metadata_category_map.contains_key(&root_metadata_category);
-					if support_metadata_category
-					{
-						let bucket_index = *metadata_category_map.get_key_value(&root_metadata_category).unwrap().1;
-						let all_metadata_buckets_ref = &mut all_metadata_buckets;
-						let current_metadata_bucket = &mut all_metadata_buckets_ref[bucket_index];
+					let found_slash = PATH_SEPARATORS.contains(&character);
 
-						if current_metadata_bucket.file_path_name == "objects"
-						{
-							object_metadata(&change_code, 
-								&name_minus_root,
-								&metadata_category_map, 
-								all_metadata_buckets_ref);
-						}
-						else if current_metadata_bucket.file_path_name == "quickActions"
-						{
-							quick_action_name(&change_code, &name_minus_root, current_metadata_bucket);
-						}
-						else if current_metadata_bucket.file_path_name == "customMetadata"
+					// If reaching the first slash, this indicates that the mode
+					// has changed from reading the root_metadata_category, to 
+					// then dealing with what lay out on the rest of the file
+					// path.
+					if found_slash && current_mode == scan_mode_root_category
+					{
+						// Shift mode to handling a given category
+						current_mode = scan_mode_read_category;
+
+						// If handling a category, determine what bucket it corresponds to,
+						// if any. If it doesn't, then we display an error that there is 
+						// an unsupported metadata category. Object child buckets are only
+						// reached through objects/, since their members need the object name.
+						let support_metadata_category = metadata_category_map.contains_key(&root_metadata_category)
+							&& !OBJECT_CHILD_CATEGORIES.contains(&root_metadata_category.as_str());
+						if support_metadata_category
 						{
-							custom_metadata_name(&name_minus_root, current_metadata_bucket);
+							let bucket_index = *metadata_category_map.get_key_value(&root_metadata_category).unwrap().1;
+							let all_metadata_buckets_ref = &mut all_metadata_buckets;
+							let current_metadata_bucket = &mut all_metadata_buckets_ref[bucket_index];
+
+							if current_metadata_bucket.file_path_name == "objects"
+							{
+								// Object children land in their own bucket (fields, listViews, ...)
+								// rather than the objects bucket.
+								let object_bucket_index = object_metadata(&change_code, &name_minus_root)
+									.and_then(|(bucket_key, member_name, change_kind)| metadata_category_map
+										.get(&bucket_key)
+										.map(|object_bucket_index| (*object_bucket_index, member_name, change_kind)));
+
+								match object_bucket_index
+								{
+									Some((object_bucket_index, member_name, change_kind)) =>
+									{
+										// Some deploys need the parent object alongside any child that changes
+										if include_parent_objects
+											&& change_kind == ChangeKind::Constructive
+											&& object_bucket_index != bucket_index
+											&& let Some((object_name, _child_name)) = member_name.split_once('.')
+										{
+											parsed &= insert_traced_member(general_context, verbose, &mut all_metadata_buckets_ref[bucket_index], object_name.to_string(), ChangeKind::Constructive, &line_file_path);
+										}
+
+										parsed &= insert_traced_member(general_context, verbose, &mut all_metadata_buckets_ref[object_bucket_index], member_name, change_kind, &line_file_path);
+									},
+									None =>
+									{
+										general_context.logger.log_error(&format!("ERROR: Object metadata path, {}, is not supported and has not been included in the manifest.\n", name_minus_root));
+										parsed = false;
+									},
+								}
+							}
+							else if current_metadata_bucket.file_path_name == "quickActions"
+							{
+								parsed &= insert_parsed_member(general_context, verbose, current_metadata_bucket, quick_action_name(&change_code, &name_minus_root), &line_file_path);
+							}
+							else if current_metadata_bucket.file_path_name == "customMetadata"
+							{
+								parsed &= insert_parsed_member(general_context, verbose, current_metadata_bucket, custom_metadata_name(&change_code, &name_minus_root), &line_file_path);
+							}
+							else if current_metadata_bucket.file_path_name == "objectTranslations"
+							{
+								parsed &= insert_parsed_member(general_context, verbose, current_metadata_bucket, object_translation_name(&change_code, &name_minus_root), &line_file_path);
+							}
+							else if current_metadata_bucket.file_path_name == "standardValueSets"
+							{
+								match standard_value_set_name(&change_code, &name_minus_root)
+								{
+									Ok(member_name) => { parsed &= insert_traced_member(general_context, verbose, current_metadata_bucket, member_name, ChangeKind::Constructive, &line_file_path); },
+									Err(reason) => general_context.logger.log_error(&format!("ERROR: {}. {} has not been included in the manifest.\n", reason, name_minus_root)),
+								}
+							}
+							else if current_metadata_bucket.file_path_name == "staticresources"
+							{
+								parsed &= insert_parsed_member(general_context, verbose, current_metadata_bucket, static_resource_name(&change_code, &name_minus_root), &line_file_path);
+							}
+							else if current_metadata_bucket.file_path_name == "flows"
+							{
+								parsed &= insert_parsed_member(general_context, verbose, current_metadata_bucket, flow_name(&change_code, &name_minus_root, keep_flow_versions), &line_file_path);
+							}
+							else
+							{
+								let parsed_member = if current_metadata_bucket.bundle
+								{ bundle_name(&change_code, &name_minus_root) }
+								else
+								{ basic_name(&change_code, &name_minus_root) };
+
+								parsed &= insert_parsed_member(general_context, verbose, current_metadata_bucket, parsed_member, &line_file_path);
+							}						
+					
+							break;
 						}
 						else
 						{
-							if !current_metadata_bucket.bundle
-							{ basic_name(&change_code, &name_minus_root, current_metadata_bucket); }
-
-							if current_metadata_bucket.bundle
-							{ bundle_name(&name_minus_root, current_metadata_bucket); }
-						}						
-						
-						break;
-					}
-					else
-					{
-						general_context.logger.log_error(&format!("ERROR: Metadata category, {}, is not supported and has not been included in the manifest.\n", root_metadata_category));
+							general_context.logger.log_error(&format!("ERROR: Metadata category, {}, is not supported and has not been included in the manifest.\n", root_metadata_category));
+							unsupported_categories.insert(root_metadata_category.clone());
+						}
+
+						continue;
 					}
 
-					continue;
+					if current_mode == scan_mode_root_category
+					{ root_metadata_category.push(character); }
 				}
-
-				if current_mode == scan_mode_root_category
-				{ root_metadata_category.push(character); }
 			}
+			else if line_file_path.len() > 0
+			{
+				out_of_scope_line_count += 1;
+			}
+
+			if !parsed
+			{ parse_failure_count += 1; }
 		}
 	}
 
-	let mut xml_file_content: String = String::with_capacity(2048);
-	xml_file_content.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
-	xml_file_content.push_str("<Package xmlns=\"http://soap.sforce.com/2006/04/metadata\">\n");
+	if parse_failure_count > 0
+	{
+		general_context.logger.log_warning(
+			&format!("WARNING: {} changed file(s) could not be parsed and were skipped, see the warnings above.\n", parse_failure_count)
+		);
+	}
 
-	let mut destructive_xml_file_content: String = String::with_capacity(2048);
-	destructive_xml_file_content.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
-	destructive_xml_file_content.push_str("<Package xmlns=\"http://soap.sforce.com/2006/04/metadata\">\n");
-	
-	for bucket in all_metadata_buckets
+	if changed_line_count > 0 && out_of_scope_line_count == changed_line_count
+	{
+		general_context.logger.log_warning(
+			&format!("WARNING: None of the {} changed files are under {}, so the manifest is empty. If the source folder is elsewhere, set it with --metadata-root.\n", changed_line_count, standard_folder)
+		);
+	}
+
+	// When a whole bundle (or static resource) is deleted, every file within it shows
+	// up in the diff, and the non-definition files would otherwise leave the bundle
+	// listed for deployment as well as destruction.
+	for bucket in &mut all_metadata_buckets
 	{
-		if bucket.files.len() == 0 && bucket.destructive_files.len() == 0 { continue; }
+		if !bucket.bundle && bucket.file_path_name != "staticresources" { continue; }
 
-		if bucket.files.len() > 0
-		{ xml_file_content.push_str("\t<types>\n"); }
+		let destructive_files = &bucket.destructive_files;
+		bucket.files.retain(|member_name| !destructive_files.contains(member_name));
+	}
 
-		if bucket.destructive_files.len() > 0
-		{ destructive_xml_file_content.push_str("\t<types>\n"); }
-		
-		// From the files as they were added to the bucket in no
-		// particular order, we'll transfer them to a Vec so that
-		// we can use the .sort() functionality
-		let mut sorted_files: Vec<String> = Vec::with_capacity(64);
-		let mut sorted_destructive_files: Vec<String> = Vec::with_capacity(64);
-		for file_name in &bucket.files
-		{
-			sorted_files.push(file_name.clone());
-		}
+	// --include-pattern and --exclude-pattern narrow the parsed members down by name.
+	// Permission sets from groups and always included components are added after this,
+	// since they're asked for explicitly.
+	let include_pattern: Option<Regex> = member_pattern(tool_context, "includepattern");
+	let exclude_pattern: Option<Regex> = member_pattern(tool_context, "excludepattern");
 
-		for file_name in &bucket.destructive_files
+	if include_pattern.is_some() || exclude_pattern.is_some()
+	{
+		for bucket in &mut all_metadata_buckets
 		{
-			sorted_destructive_files.push(file_name.clone());
-		}
+			let package_xml_name: &String = &bucket.package_xml_name;
+			let mut member_allowed = |member_name: &String| -> bool
+			{
+				let allowed: bool = include_pattern.as_ref().is_none_or(|pattern| pattern.is_match(member_name))
+					&& !exclude_pattern.as_ref().is_some_and(|pattern| pattern.is_match(member_name));
 
-		// Provides us alphabetical order from the string values
-		// of the filenames that were added.
-		sorted_files.sort();
-		sorted_destructive_files.sort();
+				if !allowed && verbose
+				{ general_context.logger.log_verbose(&format!("{}: {} left out by the member patterns\n", package_xml_name, member_name)); }
 
-		for metadata_item_name in &sorted_files
-		{
-			xml_file_content.push_str("\t\t<members>");
-			xml_file_content.push_str(&metadata_item_name);
-			xml_file_content.push_str("</members>\n");
-		}
+				return allowed;
+			};
 
-		for metadata_item_name in &sorted_destructive_files
-		{
-			destructive_xml_file_content.push_str("\t\t<members>");
-			destructive_xml_file_content.push_str(&metadata_item_name);
-			destructive_xml_file_content.push_str("</members>\n");
+			bucket.files.retain(&mut member_allowed);
+			bucket.destructive_files.retain(&mut member_allowed);
 		}
+	}
 
-		if bucket.files.len() > 0
+	// Permission set groups are checked against the feature branch source, so the
+	// permission sets they reference go out in the same deploy.
+	if tool_context.command_parameters.contains_key("includegrouppermissionsets")
+	{
+		let group_names: Vec<String> = all_metadata_buckets.iter()
+			.filter(|bucket| bucket.package_xml_name == "PermissionSetGroup")
+			.flat_map(|bucket| bucket.files.iter().cloned())
+			.collect();
+
+		match source_path
 		{
-			xml_file_content.push_str("\t\t<name>");
-			xml_file_content.push_str(&bucket.package_xml_name);
-			xml_file_content.push_str("</name>\n");
-	
-			xml_file_content.push_str("\t</types>\n");
+			Some(source_path) =>
+			{
+				let permission_set_bucket_index = metadata_category_map.get("permissionsets").copied();
+				for group_name in &group_names
+				{
+					for permission_set_name in group_permission_sets(general_context, source_path, &standard_folder, group_name)
+					{
+						if let Some(bucket_index) = permission_set_bucket_index
+						{
+							let group_source: String = format!("permission set group {}", group_name);
+							insert_traced_member(general_context, verbose, &mut all_metadata_buckets[bucket_index], permission_set_name, ChangeKind::Constructive, &group_source);
+						}
+					}
+				}
+			},
+			None if group_names.len() > 0 => general_context.logger.log_warning(
+				"WARNING: --include-group-permission-sets needs the branch source, which is only fetched with --automation git. Permission sets have not been added.\n"
+			),
+			None => {},
 		}
+	}
 
-		// TODO: Should this be separated? Branched?
-		if bucket.destructive_files.len() > 0
+	// Components that every deploy needs regardless of the diff, from --always-include
+	// and the always_include configuration variable. Types with no bucket of their own
+	// (Settings, for example) get one added for the purpose.
+	for (type_name, member_name) in always_include_components(general_context, tool_context)
+	{
+		let bucket_position = all_metadata_buckets.iter().position(|bucket| bucket.package_xml_name == type_name);
+		let bucket_position = match bucket_position
 		{
-			destructive_xml_file_content.push_str("\t\t<name>");
-			destructive_xml_file_content.push_str(&bucket.package_xml_name);
-			destructive_xml_file_content.push_str("</name>\n");
+			Some(bucket_position) => bucket_position,
+			None =>
+			{
+				all_metadata_buckets.push(MetadataBucket::new("", &type_name, false));
+				all_metadata_buckets.len() - 1
+			}
+		};
 
-			destructive_xml_file_content.push_str("\t</types>\n");
-		}
+		insert_traced_member(general_context, verbose, &mut all_metadata_buckets[bucket_position], member_name, ChangeKind::Constructive, "always include");
+	}
+
+	// A destructive-only deploy still needs a package.xml alongside destructiveChanges.xml,
+	// so rather than skipping the file, the additive side is simply left empty.
+	if tool_context.command_parameters.contains_key("onlydestructive")
+	{
+		for bucket in &mut all_metadata_buckets
+		{ bucket.files.clear(); }
+	}
+
+	if tool_context.command_parameters.contains_key("onlyadditive")
+	{
+		for bucket in &mut all_metadata_buckets
+		{ bucket.destructive_files.clear(); }
+	}
+
+	let type_counts: Vec<(String, usize, usize)> = all_metadata_buckets
+		.iter()
+		.filter(|bucket| bucket.files.len() > 0 || bucket.destructive_files.len() > 0)
+		.map(|bucket| (bucket.package_xml_name.clone(), bucket.files.len(), bucket.destructive_files.len()))
+		.collect();
+
+	let natural_sort: bool = tool_context.command_parameters.contains_key("naturalsort");
+
+	// Tabs by default, or a fixed number of spaces with --indent
+	let indent: String = indent_unit(tool_context);
+	let nested_indent: String = indent.repeat(2);
+
+	let mut xml_file_content: String = String::with_capacity(2048);
+	xml_file_content.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+	xml_file_content.push_str("<Package xmlns=\"http://soap.sforce.com/2006/04/metadata\">\n");
+
+	let mut destructive_xml_file_content: String = String::with_capacity(2048);
+	destructive_xml_file_content.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+	destructive_xml_file_content.push_str("<Package xmlns=\"http://soap.sforce.com/2006/04/metadata\">\n");
+	
+	let bucket_types_blocks: Vec<(String, String)> = all_metadata_buckets
+		.par_iter()
+		.filter(|bucket| bucket.files.len() > 0 || bucket.destructive_files.len() > 0)
+		.map(|bucket| types_blocks(bucket, natural_sort, &indent, &nested_indent))
+		.collect();
+
+	// Collecting keeps the bucket order, so the output is the same as a serial build
+	for (types_block, destructive_types_block) in &bucket_types_blocks
+	{
+		xml_file_content.push_str(types_block);
+		destructive_xml_file_content.push_str(destructive_types_block);
 	}
 
 	// Stupidly, if the category of the metadata is 'CustomLabel' then we
 	// also have to add the CustomLabels category with a hardcoded 'CustomLabels'
 	// member. Don't ask me, or this code comment, why. We don't know. No one 
 	// understands why Salesforce would do it this way. -Scott Lee
-	xml_file_content = xml_file_content.replace("<types>\n\t\t<members>CustomLabels</members>\n\t\t<name>CustomLabels</name>\n\t</types>\n",
-				"<types>\n\t\t<members>*</members>\n\t\t<name>CustomLabels</name>\n\t</types>\n");
+	xml_file_content = xml_file_content.replace(
+		&format!("<types>\n{1}<members>CustomLabels</members>\n{1}<name>CustomLabels</name>\n{0}</types>\n", indent, nested_indent),
+		&format!("<types>\n{1}<members>*</members>\n{1}<name>CustomLabels</name>\n{0}</types>\n", indent, nested_indent));
+
+	let api_version: String = manifest_api_version(general_context, tool_context);
 
-	xml_file_content.push_str("\t<version>64.0</version>\n");
+	xml_file_content.push_str(&format!("{}<version>{}</version>\n", indent, api_version));
 	xml_file_content.push_str("</Package>");
 
-	destructive_xml_file_content.push_str("\t<version>64.0</version>\n");
+	destructive_xml_file_content.push_str(&format!("{}<version>{}</version>\n", indent, api_version));
 	destructive_xml_file_content.push_str("</Package>");
 
 	return ManifestBundle{
 		manifest: xml_file_content,
-		destructive_manifest: destructive_xml_file_content
+		destructive_manifest: destructive_xml_file_content,
+		unsupported_categories,
+		changed_files,
+		type_counts
 	};
 }
 
+// Builds the <types> block for one bucket in package.xml and in
+// destructiveChanges.xml; either is empty when the bucket has no members for it.
+// Each bucket is independent of the others, so these are built in parallel.
+fn types_blocks(bucket: &MetadataBucket, natural_sort: bool, indent: &String, nested_indent: &String) -> (String, String)
+{
+	let mut types_block: String = String::with_capacity(1024);
+	let mut destructive_types_block: String = String::with_capacity(1024);
+
+	if bucket.files.len() > 0
+	{ types_block.push_str(&format!("{}<types>\n", indent)); }
+
+	if bucket.destructive_files.len() > 0
+	{ destructive_types_block.push_str(&format!("{}<types>\n", indent)); }
+	
+	// From the files as they were added to the bucket in no
+	// particular order, we'll transfer them to a Vec so that
+	// we can use the .sort() functionality
+	let mut sorted_files: Vec<String> = Vec::with_capacity(64);
+	let mut sorted_destructive_files: Vec<String> = Vec::with_capacity(64);
+	for file_name in &bucket.files
+	{
+		sorted_files.push(file_name.clone());
+	}
+
+	for file_name in &bucket.destructive_files
+	{
+		sorted_destructive_files.push(file_name.clone());
+	}
+
+	// Provides us alphabetical order from the string values
+	// of the filenames that were added, or natural order if
+	// requested with --natural-sort.
+	if natural_sort
+	{
+		sorted_files.sort_by(natural_compare);
+		sorted_destructive_files.sort_by(natural_compare);
+	}
+	else
+	{
+		sorted_files.sort();
+		sorted_destructive_files.sort();
+	}
+
+	for metadata_item_name in &sorted_files
+	{
+		types_block.push_str(&format!("{}<members>", nested_indent));
+		types_block.push_str(&xml_escape(metadata_item_name));
+		types_block.push_str("</members>\n");
+	}
+
+	for metadata_item_name in &sorted_destructive_files
+	{
+		destructive_types_block.push_str(&format!("{}<members>", nested_indent));
+		destructive_types_block.push_str(&xml_escape(metadata_item_name));
+		destructive_types_block.push_str("</members>\n");
+	}
+
+	if bucket.files.len() > 0
+	{
+		types_block.push_str(&format!("{}<name>", nested_indent));
+		types_block.push_str(&xml_escape(&bucket.package_xml_name));
+		types_block.push_str("</name>\n");
+
+		types_block.push_str(&format!("{}</types>\n", indent));
+	}
+
+	// TODO: Should this be separated? Branched?
+	if bucket.destructive_files.len() > 0
+	{
+		destructive_types_block.push_str(&format!("{}<name>", nested_indent));
+		destructive_types_block.push_str(&xml_escape(&bucket.package_xml_name));
+		destructive_types_block.push_str("</name>\n");
+
+		destructive_types_block.push_str(&format!("{}</types>\n", indent));
+	}
+
+	return (types_block, destructive_types_block);
+}
+
 fn latest_commit_has_error(latest_commit_compare: &String, latest_commit_feature: &String) -> bool
 {
 	return latest_commit_compare.len() == 0 
@@ -893,19 +1910,60 @@ fn latest_commit_has_error(latest_commit_compare: &String, latest_commit_feature
 		|| latest_commit_feature.contains("not found");
 }
 
-fn output_package_xml_file(_general_context: &mut Context, 
+// Writes the file content to a temporary file beside the destination and then
+// renames it into place. The rename is atomic on the same file system, so a
+// reader (such as a deploy job) only ever sees the old file or the complete new
+// one, never a partially written manifest.
+fn write_file_atomically(output_path: &String, file_content: &[u8]) -> std::io::Result<()>
+{
+	// Output names may include folders (manifest/package.xml), which might not exist yet
+	if let Some(parent_folder) = std::path::Path::new(output_path).parent()
+	{ file_system::create_dir_all(parent_folder)?; }
+
+	let temporary_path: String = format!("{}.{}.tmp", output_path, std::process::id());
+
+	if let Err(write_error) = file_system::write(&temporary_path, file_content)
+	{
+		let _ = file_system::remove_file(&temporary_path);
+		return Err(write_error);
+	}
+
+	if let Err(rename_error) = file_system::rename(&temporary_path, output_path)
+	{
+		let _ = file_system::remove_file(&temporary_path);
+		return Err(rename_error);
+	}
+
+	return Ok(());
+}
+
+fn output_package_xml_file(general_context: &mut Context, 
 	tool_context: &mut ToolContext, 
 	xml_content: &String,
-	filename: &String)
+	filename: &String) -> bool
 {
 	let xml_file_write_time_start = Instant::now();
 
+	// Member names come straight from file paths, so make sure nothing slipped
+	// through that would produce a document the Metadata API refuses to read.
+	if let Err(validation_error) = validate_xml_document(xml_content)
+	{
+		general_context.logger.log_error(
+			&format!("ERROR: {} is not well-formed XML ({}) and was not written.\n", filename, validation_error)
+		);
+		tool_context.exit_code = 1;
+		return false;
+	}
+
 	let string_only: bool = tool_context.command_parameters.contains_key("stringonly");
+	let dry_run: bool = tool_context.command_parameters.contains_key("dryrun");
 
-	if string_only
+	// Each printed manifest is labeled with its file name, since both are usually
+	// printed one after the other.
+	if string_only || dry_run
 	{
-		print!("xml:\n{}\n", xml_content);
-		return;
+		print!("=== {} ===\n{}\n", filename, xml_content);
+		return false;
 	}
 
 	let current_working_directory = tool_context.working_path.clone();
@@ -914,59 +1972,510 @@ fn output_package_xml_file(_general_context: &mut Context,
 	output_path.push(slash());
 	output_path.push_str(filename);
 
-	file_system::write(output_path, xml_content.as_bytes()).unwrap();
+	let mut file_content: String = xml_content.clone();
+	if tool_context.command_parameters.contains_key("trailingnewline") && !file_content.ends_with('\n')
+	{
+		file_content.push('\n');
+	}
+
+	if let Err(write_error) = write_file_atomically(&output_path, file_content.as_bytes())
+	{
+		general_context.logger.log_error(
+			&format!("ERROR: Failed to write {}: {}\n", output_path, write_error)
+		);
+		tool_context.exit_code = 1;
+		return false;
+	}
 
 	let xml_file_write_time: f64 = xml_file_write_time_start.elapsed().as_secs_f64() * 1000.0;
-	let xml_file_write_time_message: String = String::from(format!("manifest::xml file write: {}ms\n", xml_file_write_time));
+	let xml_file_write_time_message: String = format!("manifest::xml file write: {}ms\n", xml_file_write_time);
 	tool_context.time_snapshots.push(xml_file_write_time_message);
+	return true;
 }
 
-fn clean_up(_general_context: &mut Context, tool_context: &mut ToolContext)
+// Prints how many members of each metadata type are being added and deleted, with
+// any type that has deletions in the warning color so a mass delete stands out.
+fn print_type_breakdown(general_context: &mut Context, manifest_bundle: &ManifestBundle)
 {
-	let avoid_clean = tool_context.command_parameters.contains_key("noclean");
-
-	if avoid_clean { return; }
+	if manifest_bundle.type_counts.len() == 0
+	{ return; }
 
-	let clean_up_time_start = Instant::now();
+	let name_width: usize = manifest_bundle.type_counts.iter().map(|(type_name, _, _)| type_name.len()).max().unwrap_or(0);
+	let (mut total_added, mut total_deleted): (usize, usize) = (0, 0);
 
-	let current_working_directory = tool_context.working_path.clone();
-	let mut temp_path_feature: String = String::with_capacity(current_working_directory.len() + 1 + FEATURE_BRANCH_TEMP_FOLDER.len());
-	temp_path_feature.push_str(&current_working_directory);
-	temp_path_feature.push(slash());
-	temp_path_feature.push_str(FEATURE_BRANCH_TEMP_FOLDER);
+	general_context.logger.log_info("\nChanges by metadata type:\n");
+	for (type_name, added_count, deleted_count) in &manifest_bundle.type_counts
+	{
+		let type_line: String = format!("  {:<width$}  {:>5} added  {:>5} deleted\n", type_name, added_count, deleted_count, width = name_width);
 
-	let mut temp_path_compare: String = String::with_capacity(current_working_directory.len() + 1 + COMPARE_BRANCH_TEMP_FOLDER.len());
-	temp_path_compare.push_str(&current_working_directory);
-	temp_path_compare.push(slash());
-	temp_path_compare.push_str(COMPARE_BRANCH_TEMP_FOLDER);
+		if *deleted_count > 0
+		{ general_context.logger.log_highlighted(&type_line); }
+		else
+		{ general_context.logger.log_info(&type_line); }
 
-	if file_system::metadata(&temp_path_feature).is_ok() {
-		file_system::remove_dir_all(temp_path_feature).unwrap();
-	}
-	
-	if file_system::metadata(&temp_path_compare).is_ok() {
-		file_system::remove_dir_all(temp_path_compare).unwrap();
+		total_added += added_count;
+		total_deleted += deleted_count;
 	}
 
-	let clean_up_time: f64 = clean_up_time_start.elapsed().as_secs_f64() * 1000.0;
-	let clean_up_time_message: String = String::from(format!("manifest::clean up: {}ms\n", clean_up_time));
-	tool_context.time_snapshots.push(clean_up_time_message);
+	general_context.logger.log_info(&format!("  {:<width$}  {:>5} added  {:>5} deleted\n\n", "Total", total_added, total_deleted, width = name_width));
 }
 
-pub fn list_supported_metadata(tool_context: &mut ToolContext)
+// With --summary-json, writes a small JSON summary of the run for CI dashboards:
+// how many components each manifest lists, which metadata categories were skipped
+// as unsupported, and whether any manifest file was written. A run that found no
+// changes has no manifest bundle and still gets a summary, with zero counts.
+fn output_summary_json(general_context: &mut Context,
+	tool_context: &mut ToolContext,
+	manifest_bundle: Option<&ManifestBundle>,
+	manifest_written: bool)
 {
-	let metadata_buckets = common_metadata_buckets(tool_context);
-
-	print!("\n==SUPPORTED METADATA TYPES==\n");
-	for bucket in &metadata_buckets
-	{ print!("{}\n", bucket.package_xml_name); }
-	print!("\n");
-}
+	let summary_path = match tool_context.command_parameters.get("summaryjson")
+	{
+		Some(summary_path) => summary_path,
+		None => return,
+	};
+
+	let component_count = |xml_content: &String| -> usize
+	{
+		return crate::package::parse_package_xml(xml_content).values().map(|members| members.len()).sum();
+	};
+
+	let (additive_components, destructive_components, unsupported_categories) = match manifest_bundle
+	{
+		Some(manifest_bundle) => (
+			component_count(&manifest_bundle.manifest),
+			component_count(&manifest_bundle.destructive_manifest),
+			manifest_bundle.unsupported_categories.iter().cloned().collect::<Vec<String>>()
+		),
+		None => (0, 0, Vec::new()),
+	};
+
+	let mut types = serde_json::Map::new();
+	for (type_name, added_count, deleted_count) in manifest_bundle.map(|manifest_bundle| &manifest_bundle.type_counts).into_iter().flatten()
+	{
+		types.insert(type_name.clone(), json!({ "added": added_count, "deleted": deleted_count }));
+	}
+
+	let summary = json!({
+		"additive_components": additive_components,
+		"destructive_components": destructive_components,
+		"types": types,
+		"unsupported_categories": unsupported_categories,
+		"written": manifest_written,
+	});
+	let summary_content: String = serde_json::to_string_pretty(&summary).unwrap();
+
+	if tool_context.command_parameters.contains_key("dryrun")
+	{
+		print!("=== {} ===\n{}\n", summary_path, summary_content);
+		return;
+	}
+
+	// Relative paths are taken from the working path, like the manifests themselves
+	let output_path = std::path::Path::new(&tool_context.working_path).join(summary_path).display().to_string();
+
+	if let Err(write_error) = write_file_atomically(&output_path, summary_content.as_bytes())
+	{
+		general_context.logger.log_error(&format!("ERROR: Failed to write {}: {}\n", output_path, write_error));
+		tool_context.exit_code = 1;
+	}
+}
+
+// Which manifests to write, as (xml, json). Every --output-format given is written,
+// with --combined adding json. Without either, only the XML manifests are.
+fn output_formats(tool_context: &ToolContext) -> (bool, bool)
+{
+	let output_formats: Vec<&str> = match tool_context.command_parameters.get("outputformats")
+	{
+		Some(output_formats) => output_formats.split(',').collect(),
+		None => Vec::new(),
+	};
+
+	let write_json: bool = output_formats.contains(&"json") || tool_context.command_parameters.contains_key("combined");
+	let write_xml: bool = output_formats.contains(&"xml") || !write_json;
+	return (write_xml, write_json);
+}
+
+// Emits both halves of the manifest bundle as a single JSON artifact, with the
+// package.xml content under "deploy" and the destructiveChanges.xml content under
+// "destroy", so a pipeline can pass one file along to its next stage.
+fn output_combined_manifest(general_context: &mut Context,
+	tool_context: &mut ToolContext,
+	manifest_bundle: &ManifestBundle) -> bool
+{
+	let combined_write_time_start = Instant::now();
+
+	for xml_content in [&manifest_bundle.manifest, &manifest_bundle.destructive_manifest]
+	{
+		if let Err(validation_error) = validate_xml_document(xml_content)
+		{
+			general_context.logger.log_error(
+				&format!("ERROR: {} contains XML that is not well-formed ({}) and was not written.\n", COMBINED_MANIFEST_NAME, validation_error)
+			);
+			tool_context.exit_code = 1;
+			return false;
+		}
+	}
+
+	let combined_manifest = json!({
+		"deploy": manifest_bundle.manifest,
+		"destroy": manifest_bundle.destructive_manifest,
+	});
+	let combined_content: String = serde_json::to_string_pretty(&combined_manifest).unwrap();
+
+	if tool_context.command_parameters.contains_key("stringonly")
+		|| tool_context.command_parameters.contains_key("dryrun")
+	{
+		print!("=== {} ===\n{}\n", COMBINED_MANIFEST_NAME, combined_content);
+		return false;
+	}
+
+	let mut output_path: String = tool_context.working_path.clone();
+	output_path.push(slash());
+	output_path.push_str(COMBINED_MANIFEST_NAME);
+
+	if let Err(write_error) = write_file_atomically(&output_path, combined_content.as_bytes())
+	{
+		general_context.logger.log_error(
+			&format!("ERROR: Failed to write {}: {}\n", output_path, write_error)
+		);
+		tool_context.exit_code = 1;
+		return false;
+	}
+
+	let combined_write_time: f64 = combined_write_time_start.elapsed().as_secs_f64() * 1000.0;
+	let combined_write_time_message: String = format!("manifest::combined file write: {}ms\n", combined_write_time);
+	tool_context.time_snapshots.push(combined_write_time_message);
+	return true;
+}
+
+// Moves the feature branch checkout to a folder of the user's choosing, so the
+// source the diff was computed from can be inspected after the run. A relative
+// path is taken from the working path, and an existing folder is never replaced.
+// Once moved, clean up has nothing left to remove for the feature branch.
+fn keep_feature_source(general_context: &mut Context,
+	tool_context: &ToolContext,
+	feature_branch_path: &String,
+	keep_source_path: &String)
+{
+	let mut destination_path = std::path::PathBuf::from(&tool_context.working_path);
+	destination_path.push(keep_source_path);
+
+	if destination_path.exists()
+	{
+		general_context.logger.log_error(
+			&format!("ERROR: --keep-source folder {} already exists, so the feature branch source was not kept.\n", destination_path.display())
+		);
+		return;
+	}
+
+	match file_system::rename(feature_branch_path, &destination_path)
+	{
+		Ok(()) => general_context.logger.log_info(
+			&format!("Feature branch source kept at {}\n", destination_path.display())
+		),
+		Err(rename_error) => general_context.logger.log_error(
+			&format!("ERROR: Failed to move the feature branch source to {}: {}\n", destination_path.display(), rename_error)
+		),
+	}
+}
+
+// A recursive delete is only ever allowed on a real directory (not a symlink that
+// could point anywhere) whose name is exactly the expected temp folder name and
+// whose parent is exactly the working path. This keeps a misconfigured
+// working_path from turning clean up into data loss.
+fn is_expected_temp_folder(temp_path: &String, working_path: &String, temp_folder_name: &str) -> bool
+{
+	let temp_folder_metadata = match file_system::symlink_metadata(temp_path)
+	{
+		Ok(temp_folder_metadata) => temp_folder_metadata,
+		Err(_) => return false,
+	};
+
+	if !temp_folder_metadata.is_dir() { return false; }
+
+	let canonical_temp_path = match file_system::canonicalize(temp_path)
+	{
+		Ok(canonical_temp_path) => canonical_temp_path,
+		Err(_) => return false,
+	};
+
+	let canonical_working_path = match file_system::canonicalize(working_path)
+	{
+		Ok(canonical_working_path) => canonical_working_path,
+		Err(_) => return false,
+	};
+
+	let name_matches = canonical_temp_path.file_name().is_some_and(|file_name| file_name == temp_folder_name);
+	let parent_matches = canonical_temp_path.parent().is_some_and(|parent| parent == canonical_working_path);
+
+	return name_matches && parent_matches;
+}
+
+fn clean_up(general_context: &mut Context, tool_context: &mut ToolContext)
+{
+	let avoid_clean = tool_context.command_parameters.contains_key("noclean");
+
+	if avoid_clean { return; }
+
+	let clean_up_time_start = Instant::now();
+
+	let current_working_directory = tool_context.working_path.clone();
+	let feature_temp_folder_name: String = temp_folder_name(FEATURE_BRANCH_TEMP_FOLDER);
+	let compare_temp_folder_name: String = temp_folder_name(COMPARE_BRANCH_TEMP_FOLDER);
+
+	for temp_folder_name in [&feature_temp_folder_name, &compare_temp_folder_name]
+	{
+		let mut temp_path: String = String::with_capacity(current_working_directory.len() + 1 + temp_folder_name.len());
+		temp_path.push_str(&current_working_directory);
+		temp_path.push(slash());
+		temp_path.push_str(temp_folder_name);
+
+		if file_system::metadata(&temp_path).is_err() { continue; }
+
+		if !is_expected_temp_folder(&temp_path, &current_working_directory, temp_folder_name)
+		{
+			general_context.logger.log_error(
+				&format!("ERROR: Refusing to remove {} as it is not the expected {} folder directly under the working path.\n", temp_path, temp_folder_name)
+			);
+			continue;
+		}
+
+		file_system::remove_dir_all(temp_path).unwrap();
+	}
+
+	let clean_up_time: f64 = clean_up_time_start.elapsed().as_secs_f64() * 1000.0;
+	let clean_up_time_message: String = format!("manifest::clean up: {}ms\n", clean_up_time);
+	tool_context.time_snapshots.push(clean_up_time_message);
+}
+
+pub fn list_supported_metadata(tool_context: &mut ToolContext, as_json: bool)
+{
+	// Object child buckets aren't folders of their own, see sort_metadata_buckets
+	let metadata_buckets: Vec<MetadataBucket> = common_metadata_buckets(tool_context)
+		.into_iter()
+		.filter(|bucket| !OBJECT_CHILD_CATEGORIES.contains(&bucket.file_path_name.as_str()))
+		.collect();
+
+	// For wrapper scripts checking whether a type is handled before a deploy
+	if as_json
+	{
+		let folder_types: serde_json::Map<String, serde_json::Value> = metadata_buckets.iter()
+			.map(|bucket| (bucket.file_path_name.clone(), json!(bucket.package_xml_name)))
+			.collect();
+
+		print!("{}\n", serde_json::to_string_pretty(&folder_types).unwrap());
+		return;
+	}
+
+	print!("\n==SUPPORTED METADATA TYPES==\n");
+	for bucket in &metadata_buckets
+	{ print!("{}\n", bucket.package_xml_name); }
+	print!("\n");
+}
+
+// Checks every bucket's package.xml name against the embedded list of known
+// Metadata API types and prints any that don't match, along with a summary. Any
+// unknown type leaves a non-zero exit code, so the check can gate CI.
+pub fn validate_metadata_types(tool_context: &mut ToolContext)
+{
+	let metadata_buckets = common_metadata_buckets(tool_context);
+	let mut unknown_type_count: usize = 0;
+
+	print!("\n==METADATA TYPE VALIDATION==\n");
+	for bucket in &metadata_buckets
+	{
+		if KNOWN_METADATA_TYPES.contains(&bucket.package_xml_name.as_str())
+		{ continue; }
+
+		unknown_type_count += 1;
+		print!("UNKNOWN: {} (from folder {})\n", bucket.package_xml_name, bucket.file_path_name);
+	}
+
+	print!("{} of {} metadata types recognized.\n\n", metadata_buckets.len() - unknown_type_count, metadata_buckets.len());
+
+	if unknown_type_count > 0
+	{ tool_context.exit_code = 1; }
+}
+
+// Left as the placeholders from a fresh config.txt, these would otherwise be sent
+// to Bitbucket as real credentials and come back as a confusing 401. Git mode only
+// needs them to build the Bitbucket remote (git handles the password itself, and
+// the SSH agent the username too), and not at all when git_remote_url is set.
+// A --public repository needs no username either.
+pub fn unset_config_variables(tool_context: &ToolContext) -> Vec<&'static str>
+{
+	let required_variables: Vec<&'static str> = if tool_context.command_parameters.contains_key("azure")
+	{
+		vec!["azure_org", "azure_project", "azure_repo", "azure_pat"]
+	}
+	else if !tool_context.command_parameters.contains_key("git")
+	{
+		vec!["bitbucket_username", "bitbucket_app_password", "bitbucket_workspace", "bitbucket_repository"]
+	}
+	else if variable_is_set(tool_context, "git_remote_url")
+	{
+		Vec::new()
+	}
+	else if git_uses_ssh(tool_context) || git_uses_anonymous_https(tool_context)
+		|| tool_context.command_parameters.contains_key("bbuser")
+	{
+		vec!["bitbucket_workspace", "bitbucket_repository"]
+	}
+	else
+	{
+		vec!["bitbucket_username", "bitbucket_workspace", "bitbucket_repository"]
+	};
+
+	return required_variables
+		.into_iter()
+		.filter(|variable_name| !variable_is_set(tool_context, variable_name))
+		.collect();
+}
+
+// The Bitbucket REST API client for the configured workspace and repository,
+// pointed at --base-url when one is given.
+pub fn bitbucket_client(tool_context: &ToolContext) -> Bitbucket
+{
+	let empty_value: String = String::new();
+	let configuration_value = |variable_name: &str| tool_context.configuration_variables.get(variable_name).unwrap_or(&empty_value).to_string();
+
+	let mut bitbucket: Bitbucket = Bitbucket::new(
+		configuration_value("bitbucket_username"),
+		configuration_value("bitbucket_app_password"),
+		configuration_value("bitbucket_workspace"),
+		configuration_value("bitbucket_repository"));
+
+	if let Some(base_url) = tool_context.command_parameters.get("baseurl")
+	{
+		bitbucket = bitbucket.with_base_url(base_url.clone());
+	}
+
+	if variable_is_set(tool_context, "user_agent")
+	{
+		bitbucket = bitbucket.with_user_agent(configuration_value("user_agent").trim().to_string());
+	}
+
+	return bitbucket;
+}
+
+// Where the diff comes from, for messages about its configuration
+fn diff_source_name(tool_context: &ToolContext) -> &'static str
+{
+	return if tool_context.command_parameters.contains_key("azure") { "Azure DevOps" } else { "Bitbucket" };
+}
+
+// The Azure DevOps REST API client for the configured organization, project and
+// repository, pointed at --base-url when one is given.
+pub fn azure_client(tool_context: &ToolContext) -> AzureDevOps
+{
+	let empty_value: String = String::new();
+	let configuration_value = |variable_name: &str| tool_context.configuration_variables.get(variable_name).unwrap_or(&empty_value).trim().to_string();
+
+	let mut azure: AzureDevOps = AzureDevOps::new(
+		configuration_value("azure_org"),
+		configuration_value("azure_project"),
+		configuration_value("azure_repo"),
+		configuration_value("azure_pat"));
+
+	if let Some(base_url) = tool_context.command_parameters.get("baseurl")
+	{
+		azure = azure.with_base_url(base_url.clone());
+	}
+
+	if variable_is_set(tool_context, "user_agent")
+	{
+		azure = azure.with_user_agent(configuration_value("user_agent"));
+	}
+
+	return azure;
+}
 
 pub fn generate_manifest(general_context: &mut Context, 
 	tool_context: &mut ToolContext)
 {
-	let (feature_branch, compare_branch) = branch_names(general_context, tool_context);
+	// A saved patch already holds the changes, so there are no branches to resolve and
+	// nothing to fetch them with.
+	let patch_file: Option<String> = tool_context.command_parameters.get("patchfile").cloned();
+
+	let (feature_branch, compare_branch) = match &patch_file
+	{
+		Some(patch_file) => (patch_file.clone(), String::from("its base")),
+		None => match branch_names(general_context, tool_context)
+		{
+			Some(branch_names) => branch_names,
+			None =>
+			{
+				tool_context.exit_code = 1;
+				return;
+			}
+		},
+	};
+
+	let unset_variables: Vec<&str> = unset_config_variables(tool_context);
+
+	if unset_variables.len() > 0 && patch_file.is_none()
+	{
+		general_context.logger.log_error(
+			&format!("ERROR: {} config is not set up ({} missing); run with --config-set or fill in config.txt.\n", diff_source_name(tool_context), unset_variables.join(", "))
+		);
+		tool_context.exit_code = 1;
+		return;
+	}
+
+	if tool_context.command_parameters.contains_key("since") && !tool_context.command_parameters.contains_key("git")
+	{
+		general_context.logger.log_error("ERROR: --since is only supported with Git orchestration (--automation git). Exiting...\n");
+		tool_context.exit_code = 1;
+		return;
+	}
+
+	if tool_context.command_parameters.contains_key("sincelasttag") && !tool_context.command_parameters.contains_key("git")
+	{
+		general_context.logger.log_error("ERROR: --since-last-tag is only supported with Git orchestration (--automation git). Exiting...\n");
+		tool_context.exit_code = 1;
+		return;
+	}
+
+	if tool_context.command_parameters.contains_key("incremental") && !tool_context.command_parameters.contains_key("git")
+	{
+		general_context.logger.log_error("ERROR: --incremental is only supported with Git orchestration (--automation git). Exiting...\n");
+		tool_context.exit_code = 1;
+		return;
+	}
+
+	let baseline_file_path = std::path::Path::new(&tool_context.working_path).join(BASELINE_FILE_NAME);
+
+	if tool_context.command_parameters.contains_key("resetbaseline")
+	{
+		match state_without_baseline(&baseline_file_path, &feature_branch)
+		{
+			Some(state_content) =>
+			{
+				if let Err(write_error) = write_file_atomically(&baseline_file_path.display().to_string(), state_content.as_bytes())
+				{
+					general_context.logger.log_error(&format!("ERROR: Failed to write {}: {}. Exiting...\n", baseline_file_path.display(), write_error));
+					tool_context.exit_code = 1;
+					return;
+				}
+
+				general_context.logger.log_info(&format!("Cleared the incremental baseline for {}\n", feature_branch));
+			},
+			None => general_context.logger.log_info(&format!("There is no incremental baseline for {} to clear\n", feature_branch)),
+		}
+
+		if !tool_context.command_parameters.contains_key("incremental")
+		{ return; }
+	}
+
+	// A baseline taken against another compare branch covers a different set of
+	// changes, so it's only picked up when the compare branch matches.
+	let baseline: Option<Baseline> = match tool_context.command_parameters.contains_key("incremental")
+	{
+		true => read_baseline(&baseline_file_path, &feature_branch).filter(|baseline| baseline.compare_branch == compare_branch),
+		false => None,
+	};
+	let mut baseline_commit: String = String::new();
 
 	// TODO: By using a different command argument, --name-status, we can also retrieve
 	// the kind of change that was done within the diff, then differentiate between
@@ -991,6 +2500,7 @@ pub fn generate_manifest(general_context: &mut Context,
 	// This will return something like this:
 	// 604ca1dc148f3c01e6e81982c5f37710b6895a60
 	// This is the long form version of the commit ID within the git repository.
+	report_phase(general_context, tool_context, "file setup");
 	let (repository_information, feature_branch_path, compare_branch_path) = initialize_repository_information(
 		general_context, 
 		tool_context, 
@@ -998,29 +2508,63 @@ pub fn generate_manifest(general_context: &mut Context,
 		&compare_branch
 	);
 
-	let mut diffed_files_by_lines: Vec<String> = Vec::new();
+	let mut diff_output: String;
+
+	if let Some(patch_file) = &patch_file
+	{
+		general_context.logger.log_info(&format!("Reading changes from patch file {}...\n", patch_file));
+
+		// Relative paths are taken from the working path, like the manifests themselves
+		let patch_path = std::path::Path::new(&tool_context.working_path).join(patch_file);
 
-	if tool_context.command_parameters.contains_key("git") 
+		diff_output = match file_system::read(&patch_path)
+		{
+			Ok(patch_bytes) => name_status_lines(&String::from_utf8_lossy(&patch_bytes)).join("\n"),
+			Err(read_error) =>
+			{
+				general_context.logger.log_error(&format!("ERROR: Unable to read --patch-file {}: {}. Exiting...\n", patch_path.display(), read_error));
+				tool_context.exit_code = 1;
+				return;
+			}
+		};
+	}
+	else if tool_context.command_parameters.contains_key("git") 
 	{
-		print!("Using Git orchestration methodology...\n");
+		general_context.logger.log_info("Using Git orchestration methodology...\n");
 
 		// Performs the work of creating repository folders and running necessary git commands
 		// to pull in source details
+		report_phase(general_context, tool_context, "git pulling");
 		manage_branches(tool_context, &repository_information);
 
+		report_phase(general_context, tool_context, "resolving commits");
+
+		// With --feature-commit and --compare-commit, those commits are what was checked
+		// out and there's nothing left to resolve.
+		let explicit_commits: bool = tool_context.command_parameters.contains_key("featurecommit");
 		let git_rev_parse_command = &String::from("git rev-parse HEAD");
 
-		general_context.logger.log_info("For compare branch:\n");
-		let (mut latest_commit_compare, _compare_error) = run_command(
-			general_context, tool_context, &compare_branch_path, git_rev_parse_command);
+		let (mut latest_commit_compare, mut latest_commit_feature) = if explicit_commits
+		{
+			(compare_branch.clone(), feature_branch.clone())
+		}
+		else
+		{
+			general_context.logger.log_info("For compare branch:\n");
+			let (latest_commit_compare, _compare_error) = run_command(
+				general_context, tool_context, &compare_branch_path, git_rev_parse_command);
+
+			general_context.logger.log_info("For feature branch:\n");
+			let (latest_commit_feature, _feature_error) = run_command(
+				general_context, tool_context, &feature_branch_path, git_rev_parse_command);
 
-		general_context.logger.log_info("For feature branch:\n");
-		let (mut latest_commit_feature, _feature_error) = run_command(
-			general_context, tool_context, &feature_branch_path, git_rev_parse_command);
+			(latest_commit_compare, latest_commit_feature)
+		};
 
 		if latest_commit_has_error(&latest_commit_compare, &latest_commit_feature)
 		{
 			general_context.logger.log_error("ERROR: Retrieving latest commit failed. Exiting...\n");
+			tool_context.exit_code = 1;
 			return;
 		}
 
@@ -1029,41 +2573,1013 @@ pub fn generate_manifest(general_context: &mut Context,
 		latest_commit_feature = latest_commit_feature.replace("\n", "").replace(" ", "");
 		latest_commit_compare = latest_commit_compare.replace("\n", "").replace(" ", "");
 
+		// With --since, the compare endpoint is the last commit on the feature branch
+		// before the given date, rather than the head of the compare branch.
+		if tool_context.command_parameters.contains_key("since")
+		{
+			let since_date = tool_context.command_parameters.get("since").unwrap().clone();
+			let git_rev_list_command = format!("git rev-list -1 --before=\"{}\" HEAD", since_date);
+
+			general_context.logger.log_info("For --since date:\n");
+			let (commit_at_date, _since_error) = run_command(
+				general_context, tool_context, &feature_branch_path, &git_rev_list_command);
+
+			latest_commit_compare = commit_at_date.replace("\n", "").replace(" ", "");
+
+			if latest_commit_compare.len() == 0
+			{
+				general_context.logger.log_error(
+					&format!("ERROR: No commit found on {} before {}. Exiting...\n", feature_branch, since_date)
+				);
+				tool_context.exit_code = 1;
+				return;
+			}
+		}
+
+		// With --since-last-tag, the compare endpoint is the most recent tag reachable
+		// from the feature branch, such as the previous release.
+		if tool_context.command_parameters.contains_key("sincelasttag")
+		{
+			let git_describe_command = &String::from("git describe --tags --abbrev=0 HEAD");
+
+			general_context.logger.log_info("For --since-last-tag:\n");
+			let (last_tag, _describe_error) = run_command(
+				general_context, tool_context, &feature_branch_path, git_describe_command);
+
+			let last_tag: String = last_tag.trim().to_string();
+
+			if last_tag.len() == 0
+			{
+				general_context.logger.log_error(
+					&format!("ERROR: No tag is reachable from {}, so there's no last tag to compare against. Exiting...\n", feature_branch)
+				);
+				clean_up(general_context, tool_context);
+				tool_context.exit_code = 1;
+				return;
+			}
+
+			general_context.logger.log_info(&format!("Comparing against the last tag, {}\n", last_tag));
+			latest_commit_compare = last_tag;
+		}
+
+		// With --incremental, only what changed since the last manifest is diffed, and
+		// its changes are added to the baseline's below.
+		if let Some(baseline) = &baseline
+		{
+			general_context.logger.log_info(&format!("Comparing against the incremental baseline, {}\n", baseline.commit));
+			latest_commit_compare = baseline.commit.clone();
+		}
+
+		baseline_commit = latest_commit_feature.clone();
+
+		// The compare commit goes first so that files only on the feature branch come
+		// back as additions (A) and files removed by it as deletions (D). Bitbucket mode
+		// requests the same direction, see Bitbucket::get_diff.
+		report_phase(general_context, tool_context, "fetching diff");
 		let git_diff_command = format!("git --no-pager diff --name-status {} {}", latest_commit_compare, latest_commit_feature);
-		let (diffed_files_from_standard_out, diffed_files_error) = run_command(
+		let (diffed_files_from_standard_out, diffed_files_error, diff_succeeded) = run_command_with_status(
 			general_context, 
 			tool_context, 
 			&feature_branch_path, 
 			&git_diff_command);
 
-		diffed_files_by_lines = split_to_lines_vec(&diffed_files_from_standard_out);
+		// A failed diff prints nothing to stdout, which would otherwise pass for a
+		// branch with no changes.
+		if !diff_succeeded
+		{
+			general_context.logger.log_error(
+				&format!("ERROR: git diff between {} and {} failed:\n\n{}\nExiting...\n", latest_commit_compare, latest_commit_feature, diffed_files_error.trim())
+			);
+			clean_up(general_context, tool_context);
+			tool_context.exit_code = 1;
+			return;
+		}
+
+		diff_output = diffed_files_from_standard_out;
 	}
-	else 
+	else if tool_context.command_parameters.contains_key("azure")
 	{
-		print!("Using Bitbucket REST API...\n");
+		general_context.logger.log_info("Using Azure DevOps REST API...\n");
 
-		let bitbucket_username: &String = tool_context.configuration_variables.get("bitbucket_username").unwrap();
-		let bitbucket_app_password: &String = tool_context.configuration_variables.get("bitbucket_app_password").unwrap();
-		let bitbucket_workspace: &String = tool_context.configuration_variables.get("bitbucket_workspace").unwrap();
-		let bitbucket_repository: &String = tool_context.configuration_variables.get("bitbucket_repository").unwrap();
+		let azure: AzureDevOps = azure_client(tool_context);
+		let version_type: &str = if tool_context.command_parameters.contains_key("featurecommit") { "commit" } else { "branch" };
 
-		let bitbucket: Bitbucket = Bitbucket::new(bitbucket_username.to_string(), bitbucket_app_password.to_string(), bitbucket_workspace.to_string(), bitbucket_repository.to_string()); 
-		let tokio_runtime: tokio::runtime::Runtime = tokio::runtime::Runtime::new().unwrap();
-		diffed_files_by_lines = tokio_runtime.block_on(bitbucket.get_diff(&feature_branch, &compare_branch)).unwrap();
+		report_phase(general_context, tool_context, "fetching diff");
+		diff_output = match azure.get_diff_blocking(&feature_branch, &compare_branch, version_type)
+		{
+			Ok(diffed_files_by_lines) => diffed_files_by_lines.join("\n"),
+			Err(azure_error) =>
+			{
+				general_context.logger.log_error(&format!("ERROR: {}. Exiting...\n", azure_error));
+				tool_context.exit_code = 1;
+				return;
+			}
+		};
 	}
+	else 
+	{
+		general_context.logger.log_info("Using Bitbucket REST API...\n");
 
-	let parse_time_start: Instant = Instant::now();
-	let manifest_bundle: &ManifestBundle = &sort_metadata_buckets(general_context, tool_context, &diffed_files_by_lines);
+		let bitbucket: Bitbucket = bitbucket_client(tool_context);
 
-	let parsing_time: f64 = parse_time_start.elapsed().as_secs_f64() * 1000.0;
-	let parsing_time_message: String = String::from(format!("manifest::parsing: {}ms\n", parsing_time));
-	tool_context.time_snapshots.push(parsing_time_message);
+		report_phase(general_context, tool_context, "fetching diff");
+		let diff_result = if tool_context.command_parameters.contains_key("featurecommit")
+		{
+			bitbucket.get_commit_diff_blocking(&feature_branch, &compare_branch)
+		}
+		else
+		{
+			bitbucket.get_diff_blocking(&feature_branch, &compare_branch)
+		};
 
-	let package_xml_name: String = String::from("package.xml");
-	let destructive_xml_name: String = String::from("destructiveChanges.xml");
+		diff_output = match diff_result
+		{
+			Ok(diffed_files_by_lines) => diffed_files_by_lines.join("\n"),
+			Err(bitbucket_error) =>
+			{
+				general_context.logger.log_error(&format!("ERROR: {}. Exiting...\n", bitbucket_error));
+				tool_context.exit_code = 1;
+				return;
+			}
+		};
+	}
 
-	output_package_xml_file(general_context, tool_context, &manifest_bundle.manifest, &package_xml_name);
-	output_package_xml_file(general_context, tool_context, &manifest_bundle.destructive_manifest, &destructive_xml_name);
+	if tool_context.command_parameters.contains_key("incremental")
+	{
+		let baseline_changes: Vec<String> = baseline.map(|baseline| baseline.changes).unwrap_or_default();
+		diff_output = fold_name_status_lines(baseline_changes.iter().map(|change| change.as_str()).chain(split_to_lines(&diff_output))).join("\n");
+	}
 
-	clean_up(general_context, tool_context);
+	// Recorded as the next baseline once the manifests are written, before --package
+	// narrows the changes down.
+	let baseline_changes: Vec<String> = match tool_context.command_parameters.contains_key("incremental")
+	{
+		true => split_to_lines(&diff_output).map(|line| line.to_string()).collect(),
+		false => Vec::new(),
+	};
+
+	// With --package, only changes inside that package's directory are kept, so each
+	// package in a multi-package repository can be deployed on its own.
+	if let Some(package_name) = tool_context.command_parameters.get("package").cloned()
+	{
+		let package_path: String = match package_directory_path(repository_path(tool_context), &package_name)
+		{
+			Ok(package_path) => package_path,
+			Err(project_error) =>
+			{
+				general_context.logger.log_error(&format!("ERROR: --package {}: {}. Exiting...\n", package_name, project_error));
+				clean_up(general_context, tool_context);
+				tool_context.exit_code = 1;
+				return;
+			}
+		};
+
+		let package_prefix: String = format!("{}/", package_path);
+		diff_output = split_to_lines(&diff_output)
+			.filter(|line| line
+				.split(['\t', ' '])
+				.skip(1)
+				.any(|file_path| file_path.starts_with(&package_prefix)))
+			.collect::<Vec<&str>>()
+			.join("\n");
+
+		if !tool_context.command_parameters.contains_key("metadataroot")
+		{
+			tool_context.command_parameters.insert(String::from("metadataroot"), format!("{}/main/default", package_path));
+		}
+	}
+
+	// An empty diff would otherwise still produce a package.xml containing nothing
+	// but the header and version, which looks like a successful generation. Always
+	// include entries still have to be deployed, so they are written regardless.
+	let no_changes_detected: bool = split_to_lines(&diff_output).all(|line| line.trim().len() == 0);
+	let always_write: bool = tool_context.command_parameters.contains_key("alwayswrite")
+		|| tool_context.command_parameters.contains_key("alwaysinclude")
+		|| variable_is_set(tool_context, "always_include");
+
+	if no_changes_detected && !always_write
+	{
+		general_context.logger.log_info(
+			&format!("No changes detected between {} and {}, so no manifest files were written.\n", feature_branch, compare_branch)
+		);
+
+		output_summary_json(general_context, tool_context, None, false);
+
+		report_phase(general_context, tool_context, "clean up");
+		clean_up(general_context, tool_context);
+		return;
+	}
+
+	// Conflicted files (U) still go into package.xml like any modification, but the
+	// state being deployed almost certainly isn't what anyone intended.
+	let conflicted_files: Vec<&str> = split_to_lines(&diff_output)
+		.filter(|line| line.starts_with('U'))
+		.map(|line| line[1..].trim())
+		.collect();
+
+	if conflicted_files.len() > 0
+	{
+		for conflicted_file in &conflicted_files
+		{
+			general_context.logger.log_warning(&format!("WARNING: {} has a merge conflict.\n", conflicted_file));
+		}
+
+		if tool_context.command_parameters.contains_key("failonconflict")
+		{
+			general_context.logger.log_error(
+				&format!("ERROR: {} file(s) with merge conflicts found and --fail-on-conflict is set. Exiting...\n", conflicted_files.len())
+			);
+
+			clean_up(general_context, tool_context);
+			tool_context.exit_code = 1;
+			return;
+		}
+	}
+
+	report_phase(general_context, tool_context, "parsing");
+	let parse_time_start: Instant = Instant::now();
+	let source_path: Option<&String> = if tool_context.command_parameters.contains_key("git") && patch_file.is_none() { Some(&feature_branch_path) } else { None };
+	let manifest_bundle: &ManifestBundle = &sort_metadata_buckets(general_context, tool_context, split_to_lines(&diff_output), source_path);
+
+	let parsing_time: f64 = parse_time_start.elapsed().as_secs_f64() * 1000.0;
+	let parsing_time_message: String = format!("manifest::parsing: {}ms\n", parsing_time);
+	tool_context.time_snapshots.push(parsing_time_message);
+
+	// Parsing stops short on a diff too large to handle, with nothing worth writing
+	if tool_context.exit_code != 0
+	{
+		report_phase(general_context, tool_context, "clean up");
+		clean_up(general_context, tool_context);
+		return;
+	}
+
+	// With --list-files, the in-scope paths are all that's wanted, so nothing is
+	// written and the run ends here. Everything else went to standard error, so the
+	// paths can be piped straight into another tool.
+	if tool_context.command_parameters.contains_key("listfiles")
+	{
+		for changed_file in &manifest_bundle.changed_files
+		{ print!("{}\n", changed_file); }
+
+		report_phase(general_context, tool_context, "clean up");
+		clean_up(general_context, tool_context);
+		return;
+	}
+
+	// Unsupported categories, retrieval warnings and out-of-scope files only warn so
+	// that a manifest still gets written, but a strict pipeline would rather fail than
+	// deploy something incomplete.
+	if tool_context.command_parameters.contains_key("strict")
+	{
+		let problem_count: usize = general_context.logger.warning_count() + general_context.logger.error_count();
+
+		if problem_count > 0
+		{
+			general_context.logger.log_error(
+				&format!("ERROR: {} warning(s) or error(s) were logged and --strict is set, so no manifest has been written. Exiting...\n", problem_count)
+			);
+
+			clean_up(general_context, tool_context);
+			tool_context.exit_code = 1;
+			return;
+		}
+	}
+
+	print_type_breakdown(general_context, manifest_bundle);
+
+	// A wrong compare base or a bad merge can turn into a manifest that deletes much
+	// of the org, so past the --max-destructive limit nothing is written.
+	if let Some(max_destructive) = tool_context.command_parameters.get("maxdestructive").and_then(|max_destructive| max_destructive.parse::<usize>().ok())
+	{
+		let destructive_count: usize = manifest_bundle.type_counts.iter().map(|(_, _, deleted_count)| deleted_count).sum();
+
+		if destructive_count > max_destructive
+		{
+			let contributing_types: Vec<String> = manifest_bundle.type_counts
+				.iter()
+				.filter(|(_, _, deleted_count)| *deleted_count > 0)
+				.map(|(type_name, _, deleted_count)| format!("{} ({})", type_name, deleted_count))
+				.collect();
+
+			general_context.logger.log_error(
+				&format!("ERROR: {} destructive members exceed --max-destructive {}, from {}. No manifest has been written. Exiting...\n",
+					destructive_count, max_destructive, contributing_types.join(", "))
+			);
+
+			clean_up(general_context, tool_context);
+			tool_context.exit_code = 1;
+			return;
+		}
+	}
+
+	// Both names are always present, defaulting to package.xml and destructiveChanges.xml
+	let package_xml_name: String = tool_context.command_parameters.get("packagename").unwrap().clone();
+	let destructive_xml_name: String = tool_context.command_parameters.get("destructivename").unwrap().clone();
+
+	// The diff is fetched and parsed once, and written out in every format asked for
+	let (write_xml, write_json): (bool, bool) = output_formats(tool_context);
+
+	report_phase(general_context, tool_context, "xml file write");
+	let mut manifest_written: bool = false;
+	if write_json
+	{
+		manifest_written |= output_combined_manifest(general_context, tool_context, manifest_bundle);
+	}
+
+	if write_xml
+	{
+		manifest_written |= output_package_xml_file(general_context, tool_context, &manifest_bundle.manifest, &package_xml_name);
+
+		// An empty destructive manifest is just the package shell, which only adds
+		// noise when printing to the terminal.
+		let skip_empty_destructive: bool = tool_context.command_parameters.contains_key("stringonly")
+			&& !manifest_bundle.destructive_manifest.contains("<types>");
+
+		if !tool_context.command_parameters.contains_key("onlyadditive") && !skip_empty_destructive
+		{
+			manifest_written |= output_package_xml_file(general_context, tool_context, &manifest_bundle.destructive_manifest, &destructive_xml_name);
+		}
+	}
+
+	output_summary_json(general_context, tool_context, Some(manifest_bundle), manifest_written);
+
+	if tool_context.command_parameters.contains_key("incremental")
+		&& manifest_written
+		&& !tool_context.command_parameters.contains_key("dryrun")
+	{
+		let next_baseline: Baseline = Baseline { compare_branch: compare_branch.clone(), commit: baseline_commit, changes: baseline_changes };
+		let state_content: String = state_with_baseline(&baseline_file_path, &feature_branch, &next_baseline);
+
+		if let Err(write_error) = write_file_atomically(&baseline_file_path.display().to_string(), state_content.as_bytes())
+		{
+			general_context.logger.log_error(&format!("ERROR: Failed to write {}: {}\n", baseline_file_path.display(), write_error));
+			tool_context.exit_code = 1;
+		}
+	}
+
+	if tool_context.command_parameters.contains_key("git")
+		&& patch_file.is_none()
+		&& let Some(keep_source_path) = tool_context.command_parameters.get("keepsource").cloned()
+	{
+		keep_feature_source(general_context, tool_context, &feature_branch_path, &keep_source_path);
+	}
+
+	report_phase(general_context, tool_context, "clean up");
+	clean_up(general_context, tool_context);
+}
+
+#[cfg(test)]
+pub mod tests
+{
+	use super::*;
+
+	#[test]
+	fn split_to_lines_returns_nothing_for_empty_input()
+	{
+		assert_eq!(split_to_lines("").count(), 0);
+	}
+
+	#[test]
+	fn split_to_lines_keeps_a_last_line_without_a_trailing_new_line()
+	{
+		let lines: Vec<&str> = split_to_lines("M\tfirst.cls\nA\tsecond.cls").collect();
+		assert_eq!(lines, vec!["M\tfirst.cls", "A\tsecond.cls"]);
+	}
+
+	#[test]
+	fn split_to_lines_strips_carriage_returns()
+	{
+		let lines: Vec<&str> = split_to_lines("M\tfirst.cls\r\nD\tsecond.cls\r\n").collect();
+		assert_eq!(lines, vec!["M\tfirst.cls", "D\tsecond.cls"]);
+	}
+
+	#[test]
+	fn basic_name_strips_the_category_folder_and_extension()
+	{
+		let (member_name, change_kind) = basic_name(&String::from("M"), &String::from("classes/MyClass.cls-meta.xml")).unwrap();
+		assert_eq!(member_name, "MyClass");
+		assert_eq!(change_kind, ChangeKind::Constructive);
+
+		let (member_name, change_kind) = basic_name(&String::from("D"), &String::from("triggers/MyTrigger.trigger")).unwrap();
+		assert_eq!(member_name, "MyTrigger");
+		assert_eq!(change_kind, ChangeKind::Destructive);
+	}
+
+	#[test]
+	fn value_set_members_are_the_value_set_name()
+	{
+		let (member_name, change_kind) = basic_name(&String::from("A"), &String::from("globalValueSets/MyVS.globalValueSet-meta.xml")).unwrap();
+		assert_eq!(member_name, "MyVS");
+		assert_eq!(change_kind, ChangeKind::Constructive);
+
+		let standard_path = String::from("standardValueSets/LeadSource.standardValueSet-meta.xml");
+		assert_eq!(standard_value_set_name(&String::from("M"), &standard_path), Ok(String::from("LeadSource")));
+		assert!(standard_value_set_name(&String::from("D"), &standard_path).is_err());
+		assert!(standard_value_set_name(&String::from("A"), &String::from("standardValueSets/My_Set__c.standardValueSet-meta.xml")).is_err());
+
+		let package_contents = crate::package::parse_package_xml(&manifest_for(
+			"A\tforce-app/main/default/globalValueSets/MyVS.globalValueSet-meta.xml\n\
+			M\tforce-app/main/default/standardValueSets/LeadSource.standardValueSet-meta.xml\n"
+		).manifest);
+		assert_eq!(package_contents["GlobalValueSet"].iter().collect::<Vec<&String>>(), vec!["MyVS"]);
+		assert_eq!(package_contents["StandardValueSet"].iter().collect::<Vec<&String>>(), vec!["LeadSource"]);
+	}
+
+	#[test]
+	fn flow_name_drops_a_version_suffix_unless_asked_to_keep_it()
+	{
+		let flow_path = String::from("flows/My_Flow-3.flow-meta.xml");
+		assert_eq!(flow_name(&String::from("A"), &flow_path, false).unwrap().0, "My_Flow");
+		assert_eq!(flow_name(&String::from("A"), &flow_path, true).unwrap().0, "My_Flow-3");
+	}
+
+	#[test]
+	fn bundle_name_is_only_destructive_when_the_definition_is_deleted()
+	{
+		let (member_name, change_kind) = bundle_name(&String::from("D"), &String::from("lwc/myComponent/myComponent.js")).unwrap();
+		assert_eq!(member_name, "myComponent");
+		assert_eq!(change_kind, ChangeKind::Constructive);
+
+		let (member_name, change_kind) = bundle_name(&String::from("D"), &String::from("lwc/myComponent/myComponent.js-meta.xml")).unwrap();
+		assert_eq!(member_name, "myComponent");
+		assert_eq!(change_kind, ChangeKind::Destructive);
+
+		assert_eq!(bundle_name(&String::from("M"), &String::from("experiences/MySite1.site-meta.xml")).unwrap().0, "MySite1");
+	}
+
+	#[test]
+	fn static_resource_name_handles_single_files_and_folders()
+	{
+		assert_eq!(static_resource_name(&String::from("M"), &String::from("staticresources/Logo.resource")), Some((String::from("Logo"), ChangeKind::Constructive)));
+		assert_eq!(static_resource_name(&String::from("M"), &String::from("staticresources/Logo.resource-meta.xml")), Some((String::from("Logo"), ChangeKind::Constructive)));
+		assert_eq!(static_resource_name(&String::from("A"), &String::from("staticresources/ChartLib/js/chart.min.js")), Some((String::from("ChartLib"), ChangeKind::Constructive)));
+		assert_eq!(static_resource_name(&String::from("D"), &String::from("staticresources/ChartLib/js/chart.min.js")), Some((String::from("ChartLib"), ChangeKind::Constructive)));
+		assert_eq!(static_resource_name(&String::from("D"), &String::from("staticresources/ChartLib.resource-meta.xml")), Some((String::from("ChartLib"), ChangeKind::Destructive)));
+	}
+
+	#[test]
+	fn quick_action_name_keeps_the_object_prefix()
+	{
+		let parsed = quick_action_name(&String::from("M"), &String::from("quickActions/Account.New_Case.quickAction-meta.xml"));
+		assert_eq!(parsed, Some((String::from("Account.New_Case"), ChangeKind::Constructive)));
+	}
+
+	#[test]
+	fn bucket_type_names_are_known_metadata_types()
+	{
+		let unknown_types: Vec<String> = common_metadata_buckets(&mut ToolContext::new())
+			.into_iter()
+			.filter(|bucket| !KNOWN_METADATA_TYPES.contains(&bucket.package_xml_name.as_str()))
+			.map(|bucket| format!("{} (from folder {})", bucket.package_xml_name, bucket.file_path_name))
+			.collect();
+
+		assert!(unknown_types.is_empty(), "unknown metadata types: {:?}", unknown_types);
+
+		let tool_context = &mut ToolContext::new();
+		validate_metadata_types(tool_context);
+		assert_eq!(tool_context.exit_code, 0);
+	}
+
+	#[test]
+	fn object_children_land_in_their_own_types()
+	{
+		let manifest_bundle = manifest_for(
+			"M\tforce-app/main/default/objects/Account/validationRules/Foo.validationRule-meta.xml\n\
+			A\tforce-app/main/default/objects/Account/recordTypes/Business.recordType-meta.xml\n"
+		);
+
+		let package_contents = crate::package::parse_package_xml(&manifest_bundle.manifest);
+		assert_eq!(package_contents["ValidationRule"].iter().collect::<Vec<&String>>(), vec!["Account.Foo"]);
+		assert_eq!(package_contents["RecordType"].iter().collect::<Vec<&String>>(), vec!["Account.Business"]);
+	}
+
+	#[test]
+	fn a_path_no_parser_can_handle_is_skipped_and_the_rest_are_kept()
+	{
+		let general_context = &mut configure_general_context();
+		let tool_context = &mut ToolContext::new();
+		let manifest_bundle = sort_metadata_buckets(general_context, tool_context, split_to_lines(
+			"M\tforce-app/main/default/classes/Invoice.cls\n\
+			M\tforce-app/main/default/quickActions/Broken.txt\n\
+			A\tforce-app/main/default/flows/Approve_Order.flow-meta.xml\n"
+		), None);
+
+		let package_contents = crate::package::parse_package_xml(&manifest_bundle.manifest);
+		assert_eq!(package_contents["ApexClass"].iter().collect::<Vec<&String>>(), vec!["Invoice"]);
+		assert_eq!(package_contents["Flow"].iter().collect::<Vec<&String>>(), vec!["Approve_Order"]);
+		assert!(!package_contents.contains_key("QuickAction"));
+
+		// One warning for the skipped path and one for the count of skipped paths
+		assert_eq!(general_context.logger.warning_count(), 2);
+	}
+
+	#[test]
+	fn a_diff_over_the_file_limit_fails_the_run()
+	{
+		let oversized_diff: String = (0..MAXIMUM_DIFF_FILE_SIZE)
+			.map(|line_number| format!("M\tforce-app/main/default/classes/Class{}.cls\n", line_number))
+			.collect();
+
+		let general_context = &mut configure_general_context();
+		let tool_context = &mut ToolContext::new();
+		sort_metadata_buckets(general_context, tool_context, split_to_lines(&oversized_diff), None);
+		assert_eq!(tool_context.exit_code, 1);
+	}
+
+	#[test]
+	fn manifests_that_are_not_written_fail_the_run()
+	{
+		let general_context = &mut configure_general_context();
+		let tool_context = &mut ToolContext::new();
+
+		let written = output_package_xml_file(general_context, tool_context, &String::from("<Package><types></Package>"), &String::from("package.xml"));
+		assert!(!written);
+		assert_eq!(tool_context.exit_code, 1);
+	}
+
+	#[test]
+	fn object_child_folders_outside_objects_are_unsupported()
+	{
+		let manifest_bundle = manifest_for(
+			"M\tforce-app/main/default/fields/Foo.field-meta.xml\n\
+			M\tforce-app/main/default/objects/Account/fields/Bar__c.field-meta.xml\n"
+		);
+
+		let package_contents = crate::package::parse_package_xml(&manifest_bundle.manifest);
+		assert_eq!(package_contents["CustomField"].iter().collect::<Vec<&String>>(), vec!["Account.Bar__c"]);
+		assert!(manifest_bundle.unsupported_categories.contains("fields"));
+	}
+
+	#[test]
+	fn objects_and_object_translations_keep_to_their_own_types()
+	{
+		let manifest_bundle = manifest_for(
+			"A\tforce-app/main/default/objects/Invoice__c/Invoice__c.object-meta.xml\n\
+			M\tforce-app/main/default/objects/Account/fields/Region__c.field-meta.xml\n\
+			M\tforce-app/main/default/objectTranslations/Account-es/Region__c.fieldTranslation-meta.xml\n"
+		);
+
+		let package_contents = crate::package::parse_package_xml(&manifest_bundle.manifest);
+		assert_eq!(package_contents["CustomObject"].iter().collect::<Vec<&String>>(), vec!["Invoice__c"]);
+		assert_eq!(package_contents["CustomField"].iter().collect::<Vec<&String>>(), vec!["Account.Region__c"]);
+		assert_eq!(package_contents["CustomObjectTranslation"].iter().collect::<Vec<&String>>(), vec!["Account-es"]);
+	}
+
+	#[test]
+	fn member_names_are_xml_escaped()
+	{
+		let manifest_bundle = manifest_for("M\tforce-app/main/default/layouts/Account-Sales & Marketing Layout.layout-meta.xml\n");
+
+		assert!(manifest_bundle.manifest.contains("<members>Account-Sales &amp; Marketing Layout</members>"));
+		assert_eq!(validate_xml_document(&manifest_bundle.manifest), Ok(()));
+	}
+
+	#[test]
+	fn search_layouts_use_the_singular_type_name()
+	{
+		let manifest_bundle = manifest_for("M\tforce-app/main/default/searchLayouts/Account.searchLayout-meta.xml\n");
+
+		let package_contents = crate::package::parse_package_xml(&manifest_bundle.manifest);
+		assert_eq!(package_contents["SearchLayout"].iter().collect::<Vec<&String>>(), vec!["Account"]);
+		assert!(!manifest_bundle.manifest.contains("SearchLayouts"));
+	}
+
+	#[test]
+	fn custom_metadata_name_keeps_the_type_and_record()
+	{
+		let (member_name, change_kind) = custom_metadata_name(&String::from("M"), &String::from("customMetadata/Setting.Default.md-meta.xml")).unwrap();
+		assert_eq!(member_name, "Setting.Default");
+		assert_eq!(change_kind, ChangeKind::Constructive);
+
+		let manifest_bundle = manifest_for("D\tforce-app/main/default/customMetadata/Setting.Retired.md-meta.xml\n");
+		assert!(manifest_bundle.destructive_manifest.contains("<members>Setting.Retired</members>"));
+		assert!(!manifest_bundle.manifest.contains("Setting.Retired"));
+	}
+
+	#[test]
+	fn parsers_accept_backslash_separated_paths()
+	{
+		assert_eq!(custom_metadata_name(&String::from("M"), &String::from("customMetadata\\Setting.Default.md-meta.xml")).unwrap().0, "Setting.Default");
+		assert_eq!(basic_name(&String::from("M"), &String::from("classes\\MyClass.cls")).unwrap().0, "MyClass");
+		assert_eq!(bundle_name(&String::from("M"), &String::from("lwc\\myComponent\\myComponent.js")).unwrap().0, "myComponent");
+		assert_eq!(static_resource_name(&String::from("M"), &String::from("staticresources\\ChartLib\\chart.js")).unwrap().0, "ChartLib");
+		assert_eq!(
+			object_metadata(&String::from("M"), &String::from("objects\\Account\\fields\\Region__c.field-meta.xml")),
+			Some((String::from("fields"), String::from("Account.Region__c"), ChangeKind::Constructive))
+		);
+
+		let package_contents = crate::package::parse_package_xml(&manifest_for("M\tforce-app\\main\\default\\classes\\MyClass.cls\n").manifest);
+		assert_eq!(package_contents["ApexClass"].iter().collect::<Vec<&String>>(), vec!["MyClass"]);
+	}
+
+	#[test]
+	fn validate_xml_document_rejects_unbalanced_documents()
+	{
+		let manifest_bundle = manifest_for(
+			"A\tforce-app/main/default/classes/Invoice.cls\n\
+			D\tforce-app/main/default/triggers/Old.trigger\n"
+		);
+		assert_eq!(validate_xml_document(&manifest_bundle.manifest), Ok(()));
+		assert_eq!(validate_xml_document(&manifest_bundle.destructive_manifest), Ok(()));
+
+		let unclosed: String = String::from("<Package><types><members>A</members></Package>");
+		assert_eq!(validate_xml_document(&unclosed), Err(String::from("</Package> does not close <types>")));
+
+		let never_closed: String = String::from("<?xml version=\"1.0\"?>\n<Package><types>");
+		assert_eq!(validate_xml_document(&never_closed), Err(String::from("<types> is never closed")));
+
+		let two_roots: String = String::from("<Package></Package><Package></Package>");
+		assert!(validate_xml_document(&two_roots).is_err());
+
+		let bare_ampersand: String = String::from("<Package><members>Sales & Marketing</members></Package>");
+		assert!(validate_xml_document(&bare_ampersand).is_err());
+	}
+
+	#[test]
+	fn natural_compare_orders_digit_runs_by_value()
+	{
+		let compare = |left: &str, right: &str| natural_compare(&String::from(left), &String::from(right));
+
+		assert_eq!(compare("Class2", "Class10"), Ordering::Less);
+		assert_eq!(compare("Class10", "Class2"), Ordering::Greater);
+		assert_eq!(compare("Class10", "Class10"), Ordering::Equal);
+		assert_eq!(compare("Class", "Class2"), Ordering::Less);
+		assert_eq!(compare("Class7", "Class007"), Ordering::Less);
+		assert_eq!(compare("Account", "Contact"), Ordering::Less);
+		assert_eq!(compare("Zeta", "Alpha"), Ordering::Greater);
+	}
+
+	#[test]
+	fn object_metadata_routes_objects_and_their_children()
+	{
+		let parsed = object_metadata(&String::from("A"), &String::from("objects/Account/Account.object-meta.xml"));
+		assert_eq!(parsed, Some((String::from("objects"), String::from("Account"), ChangeKind::Constructive)));
+
+		let parsed = object_metadata(&String::from("D"), &String::from("objects/Account/fields/Region__c.field-meta.xml"));
+		assert_eq!(parsed, Some((String::from("fields"), String::from("Account.Region__c"), ChangeKind::Destructive)));
+
+		let parsed = object_metadata(&String::from("M"), &String::from("objects/Account/validationRules/Foo.validationRule-meta.xml"));
+		assert_eq!(parsed, Some((String::from("validationRules"), String::from("Account.Foo"), ChangeKind::Constructive)));
+
+		let parsed = object_metadata(&String::from("A"), &String::from("objects/Account/recordTypes/Business.recordType-meta.xml"));
+		assert_eq!(parsed, Some((String::from("recordTypes"), String::from("Account.Business"), ChangeKind::Constructive)));
+
+		assert_eq!(object_metadata(&String::from("M"), &String::from("objects/Account/unknown/Thing.xml")), None);
+	}
+
+	// Also used by the Bitbucket tests, to check that its diffs sort the same way
+	// git mode's do.
+	pub fn manifest_for(diff_output: &str) -> ManifestBundle
+	{
+		let general_context = &mut configure_general_context();
+		let tool_context = &mut ToolContext::new();
+		return sort_metadata_buckets(general_context, tool_context, split_to_lines(diff_output), None);
+	}
+
+	#[test]
+	fn object_and_field_changes_list_the_object_once()
+	{
+		let manifest_bundle = manifest_for(
+			"M\tforce-app/main/default/objects/Account/Account.object-meta.xml\n\
+			A\tforce-app/main/default/objects/Account/fields/Foo__c.field-meta.xml\n\
+			M\tforce-app/main/default/objects/Account/Account.object-meta.xml\n"
+		);
+
+		let package_contents = crate::package::parse_package_xml(&manifest_bundle.manifest);
+		let custom_objects: Vec<&String> = package_contents["CustomObject"].iter().collect();
+		let custom_fields: Vec<&String> = package_contents["CustomField"].iter().collect();
+
+		assert_eq!(custom_objects, vec!["Account"]);
+		assert_eq!(custom_fields, vec!["Account.Foo__c"]);
+		assert_eq!(manifest_bundle.manifest.matches("<members>Account</members>").count(), 1);
+	}
+
+	#[test]
+	fn deleting_an_object_destroys_the_object_and_each_deleted_child()
+	{
+		let manifest_bundle = manifest_for(
+			"D\tforce-app/main/default/objects/Invoice__c/Invoice__c.object-meta.xml\n\
+			D\tforce-app/main/default/objects/Invoice__c/fields/Amount__c.field-meta.xml\n\
+			D\tforce-app/main/default/objects/Invoice__c/listViews/All.listView-meta.xml\n"
+		);
+
+		let destructive_contents = crate::package::parse_package_xml(&manifest_bundle.destructive_manifest);
+		assert_eq!(destructive_contents["CustomObject"].iter().collect::<Vec<&String>>(), vec!["Invoice__c"]);
+		assert_eq!(destructive_contents["CustomField"].iter().collect::<Vec<&String>>(), vec!["Invoice__c.Amount__c"]);
+		assert_eq!(destructive_contents["ListView"].iter().collect::<Vec<&String>>(), vec!["Invoice__c.All"]);
+		assert!(!manifest_bundle.manifest.contains("<types>"));
+	}
+
+	#[test]
+	fn unpackaged_metadata_format_folders_are_recognized()
+	{
+		let manifest_bundle = manifest_for(
+			"A\tunpackaged/classes/Legacy.cls\n\
+			D\tunpackaged/triggers/OldTrigger.trigger-meta.xml\n\
+			A\tforce-app/main/default/classes/Current.cls\n"
+		);
+
+		assert!(manifest_bundle.manifest.contains("<members>Legacy</members>"));
+		assert!(manifest_bundle.manifest.contains("<members>Current</members>"));
+		assert!(manifest_bundle.destructive_manifest.contains("<members>OldTrigger</members>"));
+	}
+
+	#[test]
+	fn paths_without_a_member_name_are_skipped()
+	{
+		let manifest_bundle = manifest_for(
+			"A\tforce-app/main/default/classes/.cls\nA\tforce-app/main/default/classes/Good.cls\n"
+		);
+
+		assert!(manifest_bundle.manifest.contains("<members>Good</members>"));
+		assert!(!manifest_bundle.manifest.contains("<members></members>"));
+	}
+
+	#[test]
+	fn member_patterns_filter_parsed_members_by_name()
+	{
+		let general_context = &mut configure_general_context();
+		let tool_context = &mut ToolContext::new();
+		tool_context.command_parameters.insert(String::from("includepattern"), String::from("^Billing"));
+		tool_context.command_parameters.insert(String::from("excludepattern"), String::from("Test$"));
+
+		let manifest_bundle = sort_metadata_buckets(general_context, tool_context, split_to_lines(
+			"A\tforce-app/main/default/classes/BillingService.cls\n\
+			A\tforce-app/main/default/classes/BillingServiceTest.cls\n\
+			A\tforce-app/main/default/classes/OtherService.cls\n\
+			D\tforce-app/main/default/classes/BillingOld.cls\n"
+		), None);
+
+		assert!(manifest_bundle.manifest.contains("<members>BillingService</members>"));
+		assert!(!manifest_bundle.manifest.contains("BillingServiceTest"));
+		assert!(!manifest_bundle.manifest.contains("OtherService"));
+		assert!(manifest_bundle.destructive_manifest.contains("<members>BillingOld</members>"));
+	}
+
+	#[test]
+	fn changed_files_only_lists_paths_under_the_metadata_root()
+	{
+		let manifest_bundle = manifest_for(
+			"M\tREADME.md\n\
+			A\tforce-app/main/default/classes/Added.cls\n\
+			D\tforce-app/main/default/triggers/Removed.trigger\n"
+		);
+
+		let changed_files: Vec<&String> = manifest_bundle.changed_files.iter().collect();
+		assert_eq!(changed_files, vec!["force-app/main/default/classes/Added.cls", "force-app/main/default/triggers/Removed.trigger"]);
+	}
+
+	#[test]
+	fn overlong_diff_lines_are_skipped()
+	{
+		let diff_output: String = format!(
+			"A\tforce-app/main/default/classes/{}.cls\nA\tforce-app/main/default/classes/Kept.cls",
+			"x".repeat(MAXIMUM_DIFF_LINE_LENGTH)
+		);
+
+		let manifest_bundle = manifest_for(&diff_output);
+		assert!(manifest_bundle.manifest.contains("<members>Kept</members>"));
+		assert!(!manifest_bundle.manifest.contains("xxxx"));
+	}
+
+	#[test]
+	fn api_version_comes_from_the_project_and_is_raised_to_the_floor()
+	{
+		let project = tempfile::tempdir().unwrap();
+		file_system::write(project.path().join("sfdx-project.json"), "{\"sourceApiVersion\": \"58.0\"}").unwrap();
+
+		let general_context = &mut configure_general_context();
+		let tool_context = &mut ToolContext::new();
+		tool_context.command_parameters.insert(String::from("repopath"), project.path().display().to_string());
+		assert_eq!(manifest_api_version(general_context, tool_context), "58.0");
+
+		tool_context.command_parameters.insert(String::from("minapiversion"), String::from("62.0"));
+		assert_eq!(manifest_api_version(general_context, tool_context), "62.0");
+
+		tool_context.command_parameters.insert(String::from("minapiversion"), String::from("9.0"));
+		assert_eq!(manifest_api_version(general_context, tool_context), "58.0");
+	}
+
+	#[test]
+	fn manifests_are_identical_across_runs_and_line_orders()
+	{
+		let mut diff_lines: Vec<String> = (0..40)
+			.map(|class_number| format!("A\tforce-app/main/default/classes/Class{}.cls", class_number))
+			.collect();
+		diff_lines.extend([
+			String::from("M\tforce-app/main/default/labels/CustomLabels.labels-meta.xml"),
+			String::from("A\tforce-app/main/default/objects/Account/fields/Region__c.field-meta.xml"),
+			String::from("M\tforce-app/main/default/objects/Contact/Contact.object-meta.xml"),
+			String::from("D\tforce-app/main/default/triggers/OldTrigger.trigger"),
+			String::from("D\tforce-app/main/default/lwc/oldComponent/oldComponent.js"),
+			String::from("D\tforce-app/main/default/lwc/oldComponent/oldComponent.html"),
+			String::from("M\tforce-app/main/default/flows/Onboarding.flow-meta.xml"),
+		]);
+
+		let diff_output: String = diff_lines.join("\n");
+		let first_run = manifest_for(&diff_output);
+		let second_run = manifest_for(&diff_output);
+
+		diff_lines.reverse();
+		let reversed_run = manifest_for(&diff_lines.join("\n"));
+
+		assert_eq!(first_run.manifest, second_run.manifest);
+		assert_eq!(first_run.destructive_manifest, second_run.destructive_manifest);
+		assert_eq!(first_run.manifest, reversed_run.manifest);
+		assert_eq!(first_run.destructive_manifest, reversed_run.destructive_manifest);
+	}
+
+	#[test]
+	fn group_permission_sets_are_read_from_the_group_definition()
+	{
+		let source = tempfile::tempdir().unwrap();
+		let group_folder = source.path().join("force-app/main/default/permissionsetgroups");
+		file_system::create_dir_all(&group_folder).unwrap();
+		file_system::write(
+			group_folder.join("Sales.permissionsetgroup-meta.xml"),
+			"<PermissionSetGroup><label>Sales</label><permissionSets>Sales_Read</permissionSets><permissionSets>Sales_Edit</permissionSets></PermissionSetGroup>\n"
+		).unwrap();
+
+		let general_context = &mut configure_general_context();
+		let tool_context = &mut ToolContext::new();
+		tool_context.command_parameters.insert(String::from("includegrouppermissionsets"), String::from("--include-group-permission-sets"));
+
+		let source_path: String = source.path().display().to_string();
+		let manifest_bundle = sort_metadata_buckets(general_context, tool_context,
+			split_to_lines("M\tforce-app/main/default/permissionsetgroups/Sales.permissionsetgroup-meta.xml\n"), Some(&source_path));
+
+		let package_contents = crate::package::parse_package_xml(&manifest_bundle.manifest);
+		assert_eq!(package_contents["PermissionSetGroup"].iter().collect::<Vec<&String>>(), vec!["Sales"]);
+		assert_eq!(package_contents["PermissionSet"].iter().collect::<Vec<&String>>(), vec!["Sales_Edit", "Sales_Read"]);
+	}
+
+	#[test]
+	fn renames_destroy_the_old_path_only_when_r_is_destructive()
+	{
+		let rename_diff: &str = "R095\tforce-app/main/default/classes/Old.cls\tforce-app/main/default/classes/New.cls\n";
+
+		let manifest_bundle = manifest_for(rename_diff);
+		assert_eq!(crate::package::parse_package_xml(&manifest_bundle.destructive_manifest)["ApexClass"].iter().collect::<Vec<&String>>(), vec!["Old"]);
+
+		let general_context = &mut configure_general_context();
+		let tool_context = &mut ToolContext::new();
+		tool_context.command_parameters.insert(String::from("destructivestatuses"), String::from("D"));
+
+		let manifest_bundle = sort_metadata_buckets(general_context, tool_context, split_to_lines(rename_diff), None);
+		assert!(!manifest_bundle.destructive_manifest.contains("<types>"));
+	}
+
+	#[test]
+	fn custom_labels_are_listed_with_a_wildcard_member()
+	{
+		let labels_diff: &str = "M\tforce-app/main/default/labels/CustomLabels.labels-meta.xml\n";
+
+		let package_contents = crate::package::parse_package_xml(&manifest_for(labels_diff).manifest);
+		assert_eq!(package_contents["CustomLabels"].iter().collect::<Vec<&String>>(), vec!["*"]);
+
+		// The replacement matches on exact whitespace, so it must hold for every indent
+		for indent in ["2", "4"]
+		{
+			let general_context = &mut configure_general_context();
+			let tool_context = &mut ToolContext::new();
+			tool_context.command_parameters.insert(String::from("indent"), String::from(indent));
+
+			let manifest = sort_metadata_buckets(general_context, tool_context, split_to_lines(labels_diff), None).manifest;
+			assert!(manifest.contains("<members>*</members>"), "indent {}: {}", indent, manifest);
+			assert!(!manifest.contains("<members>CustomLabels</members>"), "indent {}: {}", indent, manifest);
+		}
+	}
+
+	#[test]
+	fn git_origin_url_leaves_the_username_out_of_a_public_remote()
+	{
+		let mut tool_context = ToolContext::new();
+		tool_context.command_parameters.insert(String::from("git"), String::from("--git"));
+		tool_context.configuration_variables.insert(String::from("bitbucket_username"), String::from("someone"));
+		tool_context.configuration_variables.insert(String::from("bitbucket_workspace"), String::from("team"));
+		tool_context.configuration_variables.insert(String::from("bitbucket_repository"), String::from("salesforce"));
+		assert_eq!(git_origin_url(&tool_context), "https://someone@bitbucket.org/team/salesforce.git");
+
+		tool_context.command_parameters.insert(String::from("public"), String::from("--public"));
+		assert_eq!(git_origin_url(&tool_context), "https://bitbucket.org/team/salesforce.git");
+	}
+
+	#[test]
+	fn output_formats_default_to_xml_and_can_ask_for_both()
+	{
+		let mut tool_context = ToolContext::new();
+		assert_eq!(output_formats(&tool_context), (true, false));
+
+		tool_context.command_parameters.insert(String::from("combined"), String::from("--combined"));
+		assert_eq!(output_formats(&tool_context), (false, true));
+
+		tool_context.command_parameters.insert(String::from("outputformats"), String::from("xml,json"));
+		assert_eq!(output_formats(&tool_context), (true, true));
+	}
+
+	fn branch_test_context(feature: Option<&str>, branch: &str) -> ToolContext
+	{
+		let mut tool_context = ToolContext::new();
+		tool_context.command_parameters.insert(String::from("branch"), String::from(branch));
+
+		if let Some(feature) = feature
+		{ tool_context.command_parameters.insert(String::from("feature"), String::from(feature)); }
+
+		return tool_context;
+	}
+
+	#[test]
+	fn select_branch_names_prefers_the_feature_flag_over_the_current_branch()
+	{
+		let tool_context = branch_test_context(Some("feature/flag"), "qa");
+		assert_eq!(select_branch_names(&tool_context, Some("checked-out")), (String::from("feature/flag"), String::from("qa")));
+	}
+
+	#[test]
+	fn select_branch_names_falls_back_to_the_current_branch()
+	{
+		let tool_context = branch_test_context(None, "main");
+		assert_eq!(select_branch_names(&tool_context, Some("feature/local")), (String::from("feature/local"), String::from("main")));
+	}
+
+	#[test]
+	fn select_branch_names_leaves_the_feature_empty_when_nothing_is_known()
+	{
+		let tool_context = branch_test_context(None, "qa");
+		assert_eq!(select_branch_names(&tool_context, None), (String::new(), String::from("qa")));
+	}
+
+	#[test]
+	fn ci_feature_branch_skips_empty_variables_in_order()
+	{
+		let ci_variables = |variable_name: &str| match variable_name
+		{
+			"BITBUCKET_BRANCH" => None,
+			"CI_COMMIT_REF_NAME" => None,
+			"GITHUB_HEAD_REF" => Some(String::new()),
+			"GITHUB_REF_NAME" => Some(String::from("feature/ci")),
+			_ => None,
+		};
+		assert_eq!(ci_feature_branch(ci_variables), Some(("GITHUB_REF_NAME", String::from("feature/ci"))));
+
+		let bitbucket_variables = |variable_name: &str| if variable_name == "BITBUCKET_BRANCH" { Some(String::from("feature/bb")) } else { Some(String::from("other")) };
+		assert_eq!(ci_feature_branch(bitbucket_variables), Some(("BITBUCKET_BRANCH", String::from("feature/bb"))));
+
+		assert_eq!(ci_feature_branch(|_variable_name| None), None);
+	}
+
+	#[test]
+	fn compare_branch_must_be_allowed_unless_forced()
+	{
+		let mut tool_context = branch_test_context(None, "feature/other");
+		assert_eq!(check_compare_branch_allowed(&tool_context, &String::from("feature/other")), Ok(()));
+
+		tool_context.configuration_variables.insert(String::from("allowed_compare_branches"), String::from("qa, uat,main"));
+		assert_eq!(check_compare_branch_allowed(&tool_context, &String::from("uat")), Ok(()));
+		assert_eq!(
+			check_compare_branch_allowed(&tool_context, &String::from("feature/other")),
+			Err(vec![String::from("qa"), String::from("uat"), String::from("main")])
+		);
+
+		tool_context.command_parameters.insert(String::from("force"), String::from("--force"));
+		assert_eq!(check_compare_branch_allowed(&tool_context, &String::from("feature/other")), Ok(()));
+	}
+
+	// Not a correctness test: compares building every <types> block serially and
+	// with rayon on a large synthetic set of buckets. Run with
+	// cargo test --release -- --ignored --nocapture types_blocks_benchmark
+	#[test]
+	#[ignore]
+	fn types_blocks_benchmark()
+	{
+		let mut buckets: Vec<MetadataBucket> = Vec::with_capacity(100);
+		for bucket_number in 0..100
+		{
+			let mut bucket = MetadataBucket::new("classes", &format!("Type{}", bucket_number), false);
+			for member_number in 0..2000
+			{
+				bucket.insert_file(format!("Member_{}_{}", bucket_number, 2000 - member_number));
+				bucket.insert_destructive_file(format!("Removed_{}_{}", bucket_number, member_number));
+			}
+			buckets.push(bucket);
+		}
+
+		let indent: String = String::from("\t");
+		let nested_indent: String = indent.repeat(2);
+
+		let serial_start = Instant::now();
+		let serial_blocks: Vec<(String, String)> = buckets.iter()
+			.map(|bucket| types_blocks(bucket, true, &indent, &nested_indent))
+			.collect();
+		let serial_time = serial_start.elapsed();
+
+		let parallel_start = Instant::now();
+		let parallel_blocks: Vec<(String, String)> = buckets.par_iter()
+			.map(|bucket| types_blocks(bucket, true, &indent, &nested_indent))
+			.collect();
+		let parallel_time = parallel_start.elapsed();
+
+		assert_eq!(serial_blocks, parallel_blocks);
+		print!("serial: {:?}, parallel: {:?}\n", serial_time, parallel_time);
+	}
 }
@@ -1,5 +1,4 @@
 pub use structopt::StructOpt;
-use std::env::args;
 use std::fmt;
 use std::str::FromStr;
 
@@ -14,11 +13,13 @@ impl fmt::Display for ParseModeError
     }
 }
 
-#[derive(Debug, StructOpt, PartialEq)]
+#[derive(Debug, Default, StructOpt, PartialEq)]
 pub enum Automation
 {
+    #[default]
     Bitbucket,
-    Git
+    Git,
+    AzureDevOps
 }
 
 impl fmt::Display for Automation
@@ -41,15 +42,288 @@ impl FromStr for Automation
             "b" => Ok(Automation::Bitbucket),
             "git" => Ok(Automation::Git),
             "g" => Ok(Automation::Git),
+            "azure" => Ok(Automation::AzureDevOps),
+            "azuredevops" => Ok(Automation::AzureDevOps),
+            "azure-devops" => Ok(Automation::AzureDevOps),
             _ => Err(ParseModeError)
         }
     }
 }
 
-impl Default for Automation
+#[derive(Debug)]
+pub struct ParseShellError;
+
+impl fmt::Display for ParseShellError
+{
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(formatter, "Invalid shell, expected one of: sh, bash, cmd, powershell, pwsh")
+    }
+}
+
+#[derive(Debug, StructOpt, PartialEq)]
+pub enum Shell
+{
+    Sh,
+    Bash,
+    Cmd,
+    Powershell,
+    Pwsh
+}
+
+impl fmt::Display for Shell
+{
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(formatter, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+impl FromStr for Shell
+{
+    type Err = ParseShellError;
+
+    fn from_str(string_value: &str) -> Result<Self, Self::Err>
+    {
+        match string_value.to_lowercase().as_str()
+        {
+            "sh" => Ok(Shell::Sh),
+            "bash" => Ok(Shell::Bash),
+            "cmd" => Ok(Shell::Cmd),
+            "powershell" => Ok(Shell::Powershell),
+            "ps" => Ok(Shell::Powershell),
+            "pwsh" => Ok(Shell::Pwsh),
+            _ => Err(ParseShellError)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseIndentError;
+
+impl fmt::Display for ParseIndentError
+{
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(formatter, "Invalid indentation, expected one of: tabs, 2, 4")
+    }
+}
+
+#[derive(Debug, StructOpt, PartialEq)]
+pub enum Indent
+{
+    Tabs,
+    TwoSpaces,
+    FourSpaces
+}
+
+impl fmt::Display for Indent
+{
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            Indent::Tabs => write!(formatter, "tabs"),
+            Indent::TwoSpaces => write!(formatter, "2"),
+            Indent::FourSpaces => write!(formatter, "4")
+        }
+    }
+}
+
+impl FromStr for Indent
+{
+    type Err = ParseIndentError;
+
+    fn from_str(string_value: &str) -> Result<Self, Self::Err>
+    {
+        match string_value.to_lowercase().as_str()
+        {
+            "tabs" => Ok(Indent::Tabs),
+            "tab" => Ok(Indent::Tabs),
+            "2" => Ok(Indent::TwoSpaces),
+            "4" => Ok(Indent::FourSpaces),
+            _ => Err(ParseIndentError)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseFormatError;
+
+impl fmt::Display for ParseFormatError
+{
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(formatter, "Invalid format, expected one of: human, json")
+    }
+}
+
+#[derive(Debug, Default, StructOpt, PartialEq)]
+pub enum Format
+{
+    #[default]
+    Human,
+    Json
+}
+
+impl fmt::Display for Format
+{
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(formatter, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+impl FromStr for Format
+{
+    type Err = ParseFormatError;
+
+    fn from_str(string_value: &str) -> Result<Self, Self::Err>
+    {
+        match string_value.to_lowercase().as_str()
+        {
+            "human" => Ok(Format::Human),
+            "json" => Ok(Format::Json),
+            _ => Err(ParseFormatError)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseOutputFormatError;
+
+impl fmt::Display for ParseOutputFormatError
+{
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(formatter, "Invalid output format, expected one of: xml, json")
+    }
+}
+
+#[derive(Debug, StructOpt, PartialEq)]
+pub enum OutputFormat
+{
+    Xml,
+    Json
+}
+
+impl fmt::Display for OutputFormat
+{
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(formatter, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+impl FromStr for OutputFormat
+{
+    type Err = ParseOutputFormatError;
+
+    fn from_str(string_value: &str) -> Result<Self, Self::Err>
+    {
+        match string_value.to_lowercase().as_str()
+        {
+            "xml" => Ok(OutputFormat::Xml),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(ParseOutputFormatError)
+        }
+    }
+}
+
+/// Comparison branch used when neither --branch nor the default_compare_branch
+/// configuration variable is given.
+pub const DEFAULT_COMPARE_BRANCH: &str = "qa";
+
+// Used for --concurrency, where zero threads would leave nothing to run the git
+// operations on.
+fn parse_concurrency(string_value: &str) -> Result<usize, String>
+{
+    match string_value.parse::<usize>()
+    {
+        Ok(thread_count) if thread_count > 0 => Ok(thread_count),
+        _ => Err(String::from("Invalid concurrency, expected a whole number of at least 1"))
+    }
+}
+
+// Used for --exclude-pattern and --include-pattern, so an invalid regular expression
+// is reported up front rather than partway through a run.
+fn parse_member_pattern(string_value: &str) -> Result<String, String>
+{
+    match regex::Regex::new(string_value)
+    {
+        Ok(_) => Ok(string_value.to_string()),
+        Err(regex_error) => Err(format!("Invalid regular expression: {}", regex_error))
+    }
+}
+
+// Used for --min-api-version, normalizing a bare major version such as 62 to 62.0
+// as it's written in package.xml.
+fn parse_api_version(string_value: &str) -> Result<String, String>
+{
+    let api_version = string_value.trim();
+    let (major_version, minor_version) = api_version.split_once('.').unwrap_or((api_version, "0"));
+
+    if major_version.len() > 0 && minor_version.len() > 0
+        && major_version.chars().all(|character| character.is_ascii_digit())
+        && minor_version.chars().all(|character| character.is_ascii_digit())
+    {
+        Ok(format!("{}.{}", major_version, minor_version))
+    }
+    else
+    {
+        Err(String::from("Invalid API version, expected a version such as 62.0"))
+    }
+}
+
+// Used for --feature-commit and --compare-commit, which take a full or abbreviated
+// commit hash rather than anything git would need to resolve first.
+fn parse_commit_hash(string_value: &str) -> Result<String, String>
+{
+    let commit_hash = string_value.trim();
+
+    if commit_hash.len() >= 7 && commit_hash.len() <= 40 && commit_hash.chars().all(|character| character.is_ascii_hexdigit())
+    {
+        Ok(commit_hash.to_lowercase())
+    }
+    else
+    {
+        Err(String::from("Invalid commit, expected a hexadecimal commit hash of 7 to 40 characters"))
+    }
+}
+
+// Only D and R can be --destructive-statuses entries. Any other status would send
+// files that are being deployed to destructiveChanges.xml as well, so a typo could
+// delete live metadata.
+fn parse_destructive_statuses(string_value: &str) -> Result<String, String>
+{
+    let mut statuses: Vec<String> = Vec::new();
+
+    for status in string_value.split(',').map(|status| status.trim().to_ascii_uppercase())
+    {
+        if status != "D" && status != "R"
+        {
+            return Err(format!("Invalid status {}, expected a comma separated list of D and R", status));
+        }
+
+        statuses.push(status);
+    }
+
+    Ok(statuses.join(","))
+}
+
+// The --since date is passed on to git through the shell, so only the characters a
+// date needs (digits, letters, spaces and : . + -) are let through.
+fn parse_since_date(string_value: &str) -> Result<String, String>
 {
-    fn default() -> Self {
-        Automation::Bitbucket
+    let since_date = string_value.trim();
+
+    if since_date.len() > 0 && since_date.chars().all(|character| character.is_ascii_alphanumeric() || " :.+-".contains(character))
+    {
+        Ok(since_date.to_string())
+    }
+    else
+    {
+        Err(String::from("Invalid date, expected only letters, digits, spaces and the characters : . + -"))
     }
 }
 
@@ -64,30 +338,299 @@ pub struct Opt
     pub feature: Option<String>,
 
     /// Comparison branch, or whatever target branch the feature branch is being merged into.
-    #[structopt(short = "b", long = "branch", default_value = "qa")]
-    pub branch: String,
+    /// Defaults to the default_compare_branch configuration variable, or "qa" if unset.
+    #[structopt(short = "b", long = "branch")]
+    pub branch: Option<String>,
+
+    /// Git mode only: compares the feature branch against its own state at the given
+    /// date (anything accepted by git's --before, e.g. "2025-06-01" or "1 week ago")
+    /// instead of against the comparison branch.
+    #[structopt(long = "since", parse(try_from_str = parse_since_date))]
+    pub since: Option<String>,
+
+    /// Git mode only: compares the feature branch against the most recent tag reachable
+    /// from it (git describe --tags --abbrev=0), e.g. everything since the last release.
+    #[structopt(long = "since-last-tag", conflicts_with_all = &["since", "compare-commit"])]
+    pub since_last_tag: bool,
+
+    /// Git mode only: remembers the feature commit each manifest was generated at (in
+    /// .sfmanifest-baseline.json in the working path), and on later runs only diffs from
+    /// there, adding the new changes to those already in the manifest.
+    #[structopt(long = "incremental", conflicts_with_all = &["since", "since-last-tag", "compare-commit", "patch-file"])]
+    pub incremental: bool,
+
+    /// Forgets the feature branch's --incremental baseline, so the next incremental run
+    /// diffs the whole branch again. On its own, nothing else is done.
+    #[structopt(long = "reset-baseline")]
+    pub reset_baseline: bool,
+
+    /// The commit to diff from, in place of the comparison branch. Given together with
+    /// --feature-commit, both commits are used as they are, with no branch resolution,
+    /// and take precedence over --feature and --branch.
+    #[structopt(long = "compare-commit", requires = "feature-commit", conflicts_with = "since", parse(try_from_str = parse_commit_hash))]
+    pub compare_commit: Option<String>,
+
+    /// The commit to diff to, in place of the feature branch. Requires --compare-commit.
+    #[structopt(long = "feature-commit", requires = "compare-commit", parse(try_from_str = parse_commit_hash))]
+    pub feature_commit: Option<String>,
 
     /// If enabled, will avoid producing package.xml and destructiveChanges.xml and instead 
-    /// only print the string contents of the package.xml manifest to the terminal.
+    /// print their labeled string contents to the terminal. An empty destructiveChanges.xml
+    /// is not printed.
     #[structopt(short = "s", long = "string-only")]
     pub string_only: bool,
 
+    /// Runs the whole generation but writes nothing, printing package.xml and
+    /// destructiveChanges.xml to the terminal under a label for each file instead.
+    #[structopt(long = "dry-run")]
+    pub dry_run: bool,
+
+    /// Source folder the metadata type folders (classes, objects, ...) live under,
+    /// relative to the repository root. Defaults to force-app/main/default.
+    #[structopt(long = "metadata-root")]
+    pub metadata_root: Option<String>,
+
+    /// Only includes changes within the packageDirectories entry of sfdx-project.json
+    /// with this package name. Unless --metadata-root is also given, the metadata root
+    /// becomes <package path>/main/default.
+    #[structopt(long = "package")]
+    pub package: Option<String>,
+
+    /// Lowest API version written into the manifests, e.g. 62.0. A lower sourceApiVersion
+    /// in sfdx-project.json (or the built-in default) is raised to it.
+    #[structopt(long = "min-api-version", parse(try_from_str = parse_api_version))]
+    pub min_api_version: Option<String>,
+
+    /// File name for the generated package.xml, relative to the working path. It may
+    /// include folders, e.g. manifest/package.xml.
+    #[structopt(long = "package-name", default_value = "package.xml")]
+    pub package_name: String,
+
+    /// File name for the generated destructiveChanges.xml, relative to the working path.
+    #[structopt(long = "destructive-name", default_value = "destructiveChanges.xml")]
+    pub destructive_name: String,
+
+    /// Collapses members that differ only by case (e.g. MyClass and myClass) within a
+    /// metadata type, keeping the casing that was seen first.
+    #[structopt(long = "dedupe-case-insensitive")]
+    pub dedupe_case_insensitive: bool,
+
+    /// Sorts members using natural ordering, so embedded numbers are compared by value
+    /// (Field_2 before Field_10) instead of the default lexicographic ordering.
+    #[structopt(long = "natural-sort")]
+    pub natural_sort: bool,
+
+    /// Indentation used in the generated XML: tabs, 2 or 4 (spaces).
+    #[structopt(long = "indent", default_value = "tabs")]
+    pub indent: Indent,
+
+    /// Ends the written package.xml and destructiveChanges.xml files with a new line.
+    #[structopt(long = "trailing-newline")]
+    pub trailing_newline: bool,
+
+    /// Writes a single deployment.json holding both the package.xml ("deploy") and
+    /// destructiveChanges.xml ("destroy") content instead of two separate files.
+    #[structopt(long = "combined")]
+    pub combined: bool,
+
+    /// The manifest files to write from a single diff: xml (package.xml and
+    /// destructiveChanges.xml, the default) or json (deployment.json, as with --combined).
+    /// Can be repeated to write both.
+    #[structopt(long = "output-format", number_of_values = 1)]
+    pub output_format: Vec<OutputFormat>,
+
+    /// Keeps a version suffix on versioned flow files (MyFlow-3.flow-meta.xml becomes
+    /// MyFlow-3) rather than the default of using the bare flow API name (MyFlow).
+    #[structopt(long = "keep-flow-versions")]
+    pub keep_flow_versions: bool,
+
+    /// Also lists an object under CustomObject whenever one of its children (fields,
+    /// validation rules, record types, ...) is added or modified.
+    #[structopt(long = "include-parent-objects")]
+    pub include_parent_objects: bool,
+
+    /// Git orchestration mode only: when a PermissionSetGroup is added or modified, also
+    /// lists the permission sets named in its definition under PermissionSet.
+    #[structopt(long = "include-group-permission-sets")]
+    pub include_group_permission_sets: bool,
+
+    /// A Type:Member pair (e.g. PermissionSet:Integration_User) to add to package.xml
+    /// on every run, whatever the diff. Can be repeated, and is combined with the comma
+    /// separated always_include configuration variable.
+    #[structopt(long = "always-include", number_of_values = 1)]
+    pub always_include: Vec<String>,
+
+    /// Leaves out of both manifests any member whose name (e.g. MyClassTest or
+    /// Account.Region__c) matches this regular expression, e.g. "Test$".
+    #[structopt(long = "exclude-pattern", parse(try_from_str = parse_member_pattern))]
+    pub exclude_pattern: Option<String>,
+
+    /// Keeps only the members whose name matches this regular expression. Applied
+    /// before --exclude-pattern.
+    #[structopt(long = "include-pattern", parse(try_from_str = parse_member_pattern))]
+    pub include_pattern: Option<String>,
+
+    /// Compares against a branch missing from the allowed_compare_branches
+    /// configuration variable anyway.
+    #[structopt(long = "force")]
+    pub force: bool,
+
+    /// Comma separated change statuses whose files go into destructiveChanges.xml,
+    /// D (deleted) and R (renamed) by default. With only D, a rename is treated as a
+    /// move and its old path isn't destroyed.
+    #[structopt(long = "destructive-statuses", parse(try_from_str = parse_destructive_statuses))]
+    pub destructive_statuses: Option<String>,
+
+    /// Writes a JSON summary of the run to this path (relative to the working path):
+    /// the number of additive and destructive components, the members added and deleted
+    /// per metadata type, any unsupported metadata categories found, and whether a
+    /// manifest was written. Written even when no changes are found.
+    #[structopt(long = "summary-json")]
+    pub summary_json: Option<String>,
+
+    /// Only deletions make it into the output: destructiveChanges.xml is generated as usual
+    /// while package.xml is written without any members.
+    #[structopt(long = "only-destructive")]
+    pub only_destructive: bool,
+
+    /// Ignores deletions entirely: only package.xml is generated, and destructiveChanges.xml
+    /// is not written.
+    #[structopt(long = "only-additive", visible_alias = "skip-destructive", conflicts_with = "only-destructive")]
+    pub only_additive: bool,
+
+    /// Stops without writing any manifest when the diff contains files with merge
+    /// conflicts, instead of only warning about them.
+    #[structopt(long = "fail-on-conflict")]
+    pub fail_on_conflict: bool,
+
+    /// Disables colored terminal output. Color is also disabled automatically when
+    /// standard out is not a terminal or the NO_COLOR environment variable is set.
+    #[structopt(long = "no-color")]
+    pub no_color: bool,
+
+    /// Encoding of log messages, on the terminal and in log.txt: human (the default) or
+    /// json, which writes one JSON object per message with its level, timestamp, phase
+    /// and message, for log aggregation. Only manifests printed by --dry-run or
+    /// --string-only are written to standard out as they are.
+    #[structopt(long = "log-format", default_value = "human")]
+    pub log_format: Format,
+
+    /// Hides the progress indicator that reports the current phase of a run. It is
+    /// also hidden automatically when standard error is not a terminal.
+    #[structopt(short = "q", long = "quiet")]
+    pub quiet: bool,
+
+    /// Prints additional detail about the run: which of the required Bitbucket
+    /// configuration variables are set, missing or still placeholders, and which
+    /// changed file produced each manifest member (Type: Member <= path).
+    #[structopt(short = "v", long = "verbose")]
+    pub verbose: bool,
+
     /// Bitbucket username to use for Git orchestration, if using Bitbucket. 
     #[structopt(short = "u", long = "bitbucket-user")]
     pub bitbucket_user: Option<String>,
 
-    /// Avoids removing temporary folders if using Git orchestration mode. When using 
-    /// API services, this does not apply (and setting it would do nothing).
+    /// Bitbucket workspace to use for this run only, overriding (but not changing) the
+    /// bitbucket_workspace configuration variable.
+    #[structopt(long = "bitbucket-workspace")]
+    pub bitbucket_workspace: Option<String>,
+
+    /// Bitbucket repository to use for this run only, overriding (but not changing) the
+    /// bitbucket_repository configuration variable.
+    #[structopt(long = "bitbucket-repository")]
+    pub bitbucket_repository: Option<String>,
+
+    /// Bitbucket app password to use for this run only. It is never written to the
+    /// configuration file, but can end up in shell history.
+    #[structopt(long = "bitbucket-app-password")]
+    pub bitbucket_app_password: Option<String>,
+
+    /// Writes package.xml and destructiveChanges.xml even when no changes are detected
+    /// between the feature and comparison branches.
+    #[structopt(short = "w", long = "always-write")]
+    pub always_write: bool,
+
+    /// Avoids removing temporary folders if using Git orchestration mode. The folders are
+    /// named _feature_branch_temp_<pid> and _compare_branch_temp_<pid> after the run's
+    /// process id. When using API services, this does not apply (and setting it would do nothing).
     #[structopt(short = "n", long = "noclean")]
     pub no_clean: bool,
 
+    /// Git orchestration mode only: after a successful run, moves the feature branch
+    /// checkout into this folder (relative to the working path) for inspection. The
+    /// folder must not already exist.
+    #[structopt(long = "keep-source")]
+    pub keep_source: Option<String>,
+
+    /// The local repository to read the current branch and sfdx-project.json from,
+    /// when it isn't the working directory. Temp folders and the generated manifests
+    /// still go in the working directory.
+    #[structopt(long = "repo-path")]
+    pub repo_path: Option<String>,
+
+    /// Git orchestration mode only: the most git operations to run at once while
+    /// fetching branches. Defaults to one per branch fetched.
+    #[structopt(long = "concurrency", parse(try_from_str = parse_concurrency))]
+    pub concurrency: Option<usize>,
+
+    /// Git orchestration mode only: fetches from Bitbucket over SSH
+    /// (git@bitbucket.org:workspace/repository.git), authenticating with the SSH agent
+    /// instead of a username. Can also be set with the git_protocol=ssh configuration variable.
+    #[structopt(long = "ssh")]
+    pub ssh: bool,
+
+    /// Git orchestration mode only: fetches a public repository anonymously over HTTPS
+    /// (https://bitbucket.org/workspace/repository.git), with no username in the URL and
+    /// no prompt for Bitbucket credentials.
+    #[structopt(long = "public", visible_alias = "anonymous", conflicts_with = "ssh")]
+    pub public: bool,
+
+    /// Bitbucket REST API mode only: sends requests to this repositories endpoint instead
+    /// of https://api.bitbucket.org/2.0/repositories, e.g. a proxy or a local mock server.
+    /// In Azure DevOps mode, replaces https://dev.azure.com instead.
+    #[structopt(long = "base-url")]
+    pub base_url: Option<String>,
+
+    /// Prints the changed files under the metadata root, one per line and nothing else
+    /// on standard out (log messages go to standard error), instead of writing any manifest.
+    #[structopt(long = "list-files")]
+    pub list_files: bool,
+
+    /// Reads the changes from a unified diff saved with git diff or git format-patch
+    /// (relative to the working path) instead of fetching them, so a reported problem
+    /// can be reproduced without the repository. No branches or credentials are needed.
+    #[structopt(long = "patch-file")]
+    pub patch_file: Option<String>,
+
+    /// Avoids running manifest generation and instead checks that the config is complete,
+    /// git is installed (git mode) or Bitbucket accepts the credentials (REST API mode),
+    /// the working path is a repository and both branches resolve, printing a checklist.
+    #[structopt(long = "preflight")]
+    pub preflight: bool,
+
     /// Avoids running manifest generation and instead lists all supported metadata 
     /// categories that will parse and result in the included manifest.
     #[structopt(short = "p", long = "supported")]
     pub list_supported_mode: bool,
 
+    /// How --supported lists the metadata: human (the default) or json, an object
+    /// mapping each source folder name to its Metadata API type name.
+    #[structopt(long = "format", requires = "list-supported-mode")]
+    pub format: Option<Format>,
+
+    /// Avoids running manifest generation and instead checks every supported metadata
+    /// category's package.xml type name against a list of known Salesforce types.
+    #[structopt(long = "validate-types")]
+    pub validate_types_mode: bool,
+
+    /// Avoids running manifest generation and instead compares two existing package.xml
+    /// files, listing the members added (+) and removed (-) per metadata type.
+    #[structopt(long = "diff-manifests", number_of_values = 2, value_names = &["original", "updated"])]
+    pub diff_manifests: Option<Vec<String>>,
+
     /// Set the automation mode for how the manifest will be generated, which defaults
-    /// to "bitbucket" but would otherwise be "git" for generic Git orchestration.
+    /// to "bitbucket" but would otherwise be "git" for generic Git orchestration, or
+    /// "azure" for the Azure DevOps Repos REST API.
     #[structopt(short = "a", long = "automation", default_value="bitbucket")]
     pub automation: Automation,
 
@@ -96,10 +639,27 @@ pub struct Opt
     #[structopt(short = "e", long = "config-set")]
     pub config_set: Option<String>,
 
+    /// Fails without writing any manifest when destructiveChanges.xml would delete more
+    /// than this many members in total, listing the types that contributed. Unlimited
+    /// by default.
+    #[structopt(long = "max-destructive")]
+    pub max_destructive: Option<usize>,
+
+    /// Strict mode: --config-set refuses to set unknown configuration variables rather
+    /// than only warning about them, and manifest generation fails without writing any
+    /// manifest when it logs a warning or skips an unsupported file.
+    #[structopt(long = "strict")]
+    pub strict: bool,
+
     /// Get all configuration values within config.txt, the configuration variable
     /// file held in the executable's same folder.
     #[structopt(short ="x", long ="config-get-all")]
     pub config_get_all: bool,
+
+    /// Shell used to run git commands: sh, bash, cmd, powershell or pwsh. Takes precedence
+    /// over the shell_program/shell_argument configuration variables when given.
+    #[structopt(long = "shell")]
+    pub shell: Option<Shell>,
 }
 
 impl Opt
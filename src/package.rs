@@ -0,0 +1,133 @@
+// Reading of existing package.xml manifests, as opposed to manifest.rs which
+// generates them from a diff.
+
+// FILE SYSTEM
+use std::fs as file_system;
+
+// COLLECTIONS
+use std::collections::{BTreeMap, BTreeSet};
+
+// Metadata type name to the set of members listed under it. BTree collections
+// are used so that anything printed from them comes out in a stable order.
+pub type PackageContents = BTreeMap<String, BTreeSet<String>>;
+
+fn xml_unescape(value: &str) -> String
+{
+	return value
+		.replace("&lt;", "<")
+		.replace("&gt;", ">")
+		.replace("&quot;", "\"")
+		.replace("&apos;", "'")
+		.replace("&amp;", "&");
+}
+
+// Returns the text content of every <element_name>...</element_name> in the
+// given XML fragment, in document order.
+pub fn element_values(xml_fragment: &str, element_name: &str) -> Vec<String>
+{
+	let opening_tag: String = format!("<{}>", element_name);
+	let closing_tag: String = format!("</{}>", element_name);
+
+	let mut values: Vec<String> = Vec::with_capacity(16);
+	let mut remaining: &str = xml_fragment;
+
+	while let Some(opening_index) = remaining.find(&opening_tag)
+	{
+		remaining = &remaining[opening_index + opening_tag.len()..];
+
+		let closing_index = match remaining.find(&closing_tag)
+		{
+			Some(closing_index) => closing_index,
+			None => break,
+		};
+
+		values.push(xml_unescape(remaining[..closing_index].trim()));
+		remaining = &remaining[closing_index + closing_tag.len()..];
+	}
+
+	return values;
+}
+
+// Parses the <types> blocks of a package.xml document. This only understands the
+// structure this tool (and sf) write for manifests, not arbitrary XML.
+pub fn parse_package_xml(package_xml_content: &str) -> PackageContents
+{
+	let mut package_contents: PackageContents = BTreeMap::new();
+
+	for types_block in element_values(package_xml_content, "types")
+	{
+		let type_name = match element_values(&types_block, "name").pop()
+		{
+			Some(type_name) => type_name,
+			None => continue,
+		};
+
+		let members = package_contents.entry(type_name).or_default();
+		for member_name in element_values(&types_block, "members")
+		{
+			members.insert(member_name);
+		}
+	}
+
+	return package_contents;
+}
+
+pub fn read_package_xml(file_path: &String) -> Result<PackageContents, String>
+{
+	let package_xml_content = file_system::read_to_string(file_path)
+		.map_err(|read_error| format!("unable to read {}: {}", file_path, read_error))?;
+
+	// Anything without a <Package> root would otherwise parse as an empty manifest
+	if !package_xml_content.contains("<Package")
+	{ return Err(format!("{} is not a package.xml manifest", file_path)); }
+
+	return Ok(parse_package_xml(&package_xml_content));
+}
+
+// Prints, per metadata type, the members present in the second manifest but not
+// the first (+) and those present in the first but not the second (-). Returns
+// false when either manifest cannot be read.
+pub fn diff_manifests(original_path: &String, updated_path: &String) -> bool
+{
+	let original_contents = match read_package_xml(original_path)
+	{
+		Ok(original_contents) => original_contents,
+		Err(read_error) => { print!("ERROR: {}\n", read_error); return false; }
+	};
+
+	let updated_contents = match read_package_xml(updated_path)
+	{
+		Ok(updated_contents) => updated_contents,
+		Err(read_error) => { print!("ERROR: {}\n", read_error); return false; }
+	};
+
+	let empty_members: BTreeSet<String> = BTreeSet::new();
+	let mut type_names: BTreeSet<&String> = original_contents.keys().collect();
+	type_names.extend(updated_contents.keys());
+
+	let mut difference_count: usize = 0;
+
+	print!("\n==MANIFEST DIFFERENCES==\n{} -> {}\n\n", original_path, updated_path);
+	for type_name in type_names
+	{
+		let original_members = original_contents.get(type_name).unwrap_or(&empty_members);
+		let updated_members = updated_contents.get(type_name).unwrap_or(&empty_members);
+
+		let added_members: Vec<&String> = updated_members.difference(original_members).collect();
+		let removed_members: Vec<&String> = original_members.difference(updated_members).collect();
+
+		if added_members.len() == 0 && removed_members.len() == 0 { continue; }
+
+		print!("{}\n", type_name);
+		for member_name in &added_members { print!("\t+ {}\n", member_name); }
+		for member_name in &removed_members { print!("\t- {}\n", member_name); }
+
+		difference_count += added_members.len() + removed_members.len();
+	}
+
+	if difference_count == 0
+	{ print!("No differences.\n"); }
+
+	print!("\n");
+	return true;
+}
@@ -0,0 +1,274 @@
+// Reading of saved unified diffs (git diff or git format-patch output) for
+// --patch-file, turning their file headers into the same name-status lines that
+// git diff --name-status prints, and folding consecutive sets of those lines into
+// one for --incremental.
+
+// COLLECTION TYPES
+use std::collections::BTreeMap;
+
+// One file's header within the patch, filled in line by line until its first hunk
+struct FileHeader
+{
+	status: String,
+	old_path: String,
+	new_path: String,
+}
+
+// A path as written after a/ or b/, which git quotes when it holds unusual characters
+fn header_path(path_text: &str) -> String
+{
+	let unquoted: &str = path_text.trim().trim_matches('"');
+
+	return match unquoted.split_once('/')
+	{
+		Some((_prefix, path)) => path.to_string(),
+		None => unquoted.to_string(),
+	};
+}
+
+// The two paths of a "diff --git a/<old> b/<new>" line. Paths may contain spaces,
+// or even " b/", so an unchanged path is found by splitting where both halves
+// match, and only otherwise at the last " b/". For "a/X b/X" the " b/" starts
+// halfway through the odd length left once the space is taken out.
+fn diff_git_paths(header_paths: &str) -> (String, String)
+{
+	if header_paths.len() > 5 && !header_paths.len().is_multiple_of(2)
+	{
+		let half_length: usize = (header_paths.len() - 1) / 2;
+
+		if header_paths.is_char_boundary(half_length)
+			&& header_paths.is_char_boundary(half_length + 3)
+			&& header_paths[half_length..].starts_with(" b/")
+			&& header_paths[2..half_length] == header_paths[half_length + 3..]
+		{
+			let path: String = header_path(&header_paths[..half_length]);
+			return (path.clone(), path);
+		}
+	}
+
+	return match header_paths.rfind(" b/")
+	{
+		Some(split_index) => (header_path(&header_paths[..split_index]), header_path(&header_paths[split_index..])),
+		None => (header_path(header_paths), header_path(header_paths)),
+	};
+}
+
+// Records a finished file header. A patch series can touch the same file in several
+// commits, so those changes are folded into what the series did overall.
+fn record_change(changes: &mut BTreeMap<String, (String, String)>, file_header: FileHeader)
+{
+	// A file that was only added earlier never existed before the series, so renaming
+	// it is the same as adding it under the new name.
+	if file_header.status.starts_with('R')
+		&& changes.get(&file_header.old_path).is_some_and(|(status, _)| status == "A")
+	{
+		changes.remove(&file_header.old_path);
+		changes.insert(file_header.new_path, (String::from("A"), String::new()));
+		return;
+	}
+
+	if file_header.status.starts_with('R') || file_header.status.starts_with('C')
+	{
+		changes.insert(file_header.new_path.clone(), (file_header.status, file_header.old_path));
+		return;
+	}
+
+	let earlier_status: Option<String> = changes.get(&file_header.new_path).map(|(status, _)| status.clone());
+	let folded_status: Option<&str> = match (earlier_status.as_deref(), file_header.status.as_str())
+	{
+		(Some("A"), "D") => None,
+		(Some("A"), _) => Some("A"),
+		(Some("D"), "A") => Some("M"),
+		(_, status) => Some(status),
+	};
+
+	match folded_status
+	{
+		Some(status) => { changes.insert(file_header.new_path, (status.to_string(), String::new())); },
+		None => { changes.remove(&file_header.new_path); },
+	}
+}
+
+// Returns a name-status line (e.g. "A\tforce-app/...") for every file the patch
+// changes. Statuses come from the extended header lines: new file mode (A),
+// deleted file mode (D), rename from/to (R plus the similarity index), copy
+// from/to (C), and M for anything else.
+pub fn name_status_lines(patch_content: &str) -> Vec<String>
+{
+	let mut changes: BTreeMap<String, (String, String)> = BTreeMap::new();
+	let mut current_header: Option<FileHeader> = None;
+	let mut similarity: String = String::from("100");
+
+	for line in patch_content.lines()
+	{
+		if let Some(header_paths) = line.strip_prefix("diff --git ")
+		{
+			if let Some(file_header) = current_header.take()
+			{ record_change(&mut changes, file_header); }
+
+			let (old_path, new_path) = diff_git_paths(header_paths);
+			current_header = Some(FileHeader { status: String::from("M"), old_path, new_path });
+			similarity = String::from("100");
+			continue;
+		}
+
+		// Hunk content can hold lines that look like headers, so only the lines
+		// between "diff --git" and the first hunk are read.
+		let Some(file_header) = current_header.as_mut()
+		else { continue; };
+
+		if line.starts_with("@@") || line.starts_with("GIT binary patch")
+		{
+			record_change(&mut changes, current_header.take().unwrap());
+		}
+		else if line.starts_with("new file mode")
+		{ file_header.status = String::from("A"); }
+		else if line.starts_with("deleted file mode")
+		{ file_header.status = String::from("D"); }
+		else if let Some(similarity_index) = line.strip_prefix("similarity index ")
+		{ similarity = format!("{:0>3}", similarity_index.trim_end_matches('%')); }
+		else if let Some(old_path) = line.strip_prefix("rename from ")
+		{
+			file_header.status = format!("R{}", similarity);
+			file_header.old_path = old_path.trim_matches('"').to_string();
+		}
+		else if let Some(new_path) = line.strip_prefix("rename to ")
+		{ file_header.new_path = new_path.trim_matches('"').to_string(); }
+		else if let Some(old_path) = line.strip_prefix("copy from ")
+		{
+			file_header.status = format!("C{}", similarity);
+			file_header.old_path = old_path.trim_matches('"').to_string();
+		}
+		else if let Some(new_path) = line.strip_prefix("copy to ")
+		{ file_header.new_path = new_path.trim_matches('"').to_string(); }
+	}
+
+	if let Some(file_header) = current_header.take()
+	{ record_change(&mut changes, file_header); }
+
+	return changes_to_lines(changes);
+}
+
+// Folds name-status lines from consecutive diffs, earliest first, into what they
+// changed overall, the same way the commits of a patch series are folded.
+pub fn fold_name_status_lines<'a>(name_status_lines: impl Iterator<Item = &'a str>) -> Vec<String>
+{
+	let mut changes: BTreeMap<String, (String, String)> = BTreeMap::new();
+
+	for line in name_status_lines
+	{
+		let fields: Vec<&str> = line.trim_end().split('\t').collect();
+
+		let file_header: FileHeader = match fields.as_slice()
+		{
+			[status, old_path, new_path] => FileHeader { status: status.to_string(), old_path: old_path.to_string(), new_path: new_path.to_string() },
+			[status, path] if status.len() > 0 => FileHeader { status: status.to_string(), old_path: path.to_string(), new_path: path.to_string() },
+			_ => continue,
+		};
+
+		record_change(&mut changes, file_header);
+	}
+
+	return changes_to_lines(changes);
+}
+
+fn changes_to_lines(changes: BTreeMap<String, (String, String)>) -> Vec<String>
+{
+	return changes
+		.into_iter()
+		.map(|(new_path, (status, old_path))| match old_path.len()
+		{
+			0 => format!("{}\t{}", status, new_path),
+			_ => format!("{}\t{}\t{}", status, old_path, new_path),
+		})
+		.collect();
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn file_headers_become_name_status_lines()
+	{
+		let patch_content: &str = "\
+From 9b1e2f7 Mon Sep 17 00:00:00 2001
+Subject: [PATCH] Add an invoice class
+
+diff --git a/force-app/main/default/classes/Invoice.cls b/force-app/main/default/classes/Invoice.cls
+new file mode 100644
+index 0000000..e69de29
+--- /dev/null
++++ b/force-app/main/default/classes/Invoice.cls
+@@ -0,0 +1,2 @@
++diff --git a/not/a/header b/not/a/header
++deleted file mode 100644
+diff --git a/force-app/main/default/classes/My Class.cls b/force-app/main/default/classes/My Class.cls
+index 1111111..2222222 100644
+--- a/force-app/main/default/classes/My Class.cls
++++ b/force-app/main/default/classes/My Class.cls
+@@ -1 +1 @@
+-old
++new
+diff --git a/force-app/main/default/triggers/Old.trigger b/force-app/main/default/triggers/Old.trigger
+deleted file mode 100644
+index 3333333..0000000
+diff --git a/force-app/main/default/classes/Before.cls b/force-app/main/default/classes/After.cls
+similarity index 95%
+rename from force-app/main/default/classes/Before.cls
+rename to force-app/main/default/classes/After.cls
+";
+
+		assert_eq!(name_status_lines(patch_content), vec![
+			"R095\tforce-app/main/default/classes/Before.cls\tforce-app/main/default/classes/After.cls",
+			"A\tforce-app/main/default/classes/Invoice.cls",
+			"M\tforce-app/main/default/classes/My Class.cls",
+			"D\tforce-app/main/default/triggers/Old.trigger",
+		]);
+	}
+
+	#[test]
+	fn unchanged_paths_holding_b_slash_split_in_half()
+	{
+		assert_eq!(
+			diff_git_paths("a/dir b/My.cls b/dir b/My.cls"),
+			(String::from("dir b/My.cls"), String::from("dir b/My.cls"))
+		);
+		assert_eq!(
+			diff_git_paths("a/classes/Before.cls b/classes/After.cls"),
+			(String::from("classes/Before.cls"), String::from("classes/After.cls"))
+		);
+	}
+
+	#[test]
+	fn a_patch_series_folds_repeated_changes_to_a_file()
+	{
+		let patch_content: &str = "\
+diff --git a/classes/Temp.cls b/classes/Temp.cls
+new file mode 100644
+diff --git a/classes/Kept.cls b/classes/Kept.cls
+new file mode 100644
+diff --git a/classes/Kept.cls b/classes/Kept.cls
+index 1111111..2222222 100644
+diff --git a/classes/Temp.cls b/classes/Temp.cls
+deleted file mode 100644
+";
+
+		assert_eq!(name_status_lines(patch_content), vec!["A\tclasses/Kept.cls"]);
+	}
+
+	#[test]
+	fn later_name_status_lines_fold_into_earlier_ones()
+	{
+		let baseline_lines: &str = "A\tclasses/Added.cls\nM\tclasses/Changed.cls\nD\tclasses/Gone.cls\nA\tclasses/Draft.cls";
+		let new_lines: &str = "R090\tclasses/Added.cls\tclasses/Renamed.cls\nD\tclasses/Changed.cls\nA\tclasses/Gone.cls\nD\tclasses/Draft.cls\nM\tclasses/Other.cls";
+
+		assert_eq!(fold_name_status_lines(baseline_lines.lines().chain(new_lines.lines())), vec![
+			"D\tclasses/Changed.cls",
+			"M\tclasses/Gone.cls",
+			"M\tclasses/Other.cls",
+			"A\tclasses/Renamed.cls",
+		]);
+	}
+}
@@ -0,0 +1,205 @@
+// The --preflight checklist, which checks everything a manifest run depends on
+// (config, git or REST API access, the local repository and both branches) up
+// front, without fetching or writing anything.
+
+use crate::{Context, ToolContext};
+use crate::azure::AzureDevOpsError;
+use crate::bitbucket::BitbucketError;
+use crate::manifest::{azure_client, bitbucket_client, branch_names, git_origin_url, repository_path, unset_config_variables};
+use crate::system::run_command;
+
+// Records one line of the checklist, a failure with the reason after it
+fn report_check(failed_checks: &mut usize, check_name: &str, result: Result<(), String>)
+{
+	match result
+	{
+		Ok(()) => print!("[PASS] {}\n", check_name),
+		Err(reason) =>
+		{
+			print!("[FAIL] {}: {}\n", check_name, reason);
+			*failed_checks += 1;
+		}
+	}
+}
+
+fn check_config(tool_context: &ToolContext) -> Result<(), String>
+{
+	let unset_variables: Vec<&str> = unset_config_variables(tool_context);
+
+	if unset_variables.len() > 0
+	{
+		return Err(format!("{} missing, run with --config-set or fill in config.txt", unset_variables.join(", ")));
+	}
+
+	return Ok(());
+}
+
+fn check_git_installed(general_context: &mut Context, tool_context: &ToolContext) -> Result<(), String>
+{
+	let (git_version, git_error) = run_command(general_context, tool_context, &tool_context.working_path, &String::from("git --version"));
+
+	if !git_version.starts_with("git version")
+	{
+		return Err(format!("git --version failed ({})", git_error.trim()));
+	}
+
+	return Ok(());
+}
+
+fn check_local_repository(general_context: &mut Context, tool_context: &ToolContext) -> Result<(), String>
+{
+	let local_repository_path: &String = repository_path(tool_context);
+	let (inside_work_tree, _git_error) = run_command(general_context, tool_context, local_repository_path, &String::from("git rev-parse --is-inside-work-tree"));
+
+	if inside_work_tree.trim() != "true"
+	{
+		return Err(format!("{} is not inside a git repository", local_repository_path));
+	}
+
+	return Ok(());
+}
+
+// A branch resolves in git mode when the remote lists it, which needs no clone
+fn check_remote_branch(general_context: &mut Context, tool_context: &ToolContext, branch_name: &String) -> Result<(), String>
+{
+	let git_ls_remote_command: String = format!("git ls-remote --heads {} {}", git_origin_url(tool_context), branch_name);
+	let (remote_heads, git_error) = run_command(general_context, tool_context, &tool_context.working_path, &git_ls_remote_command);
+
+	if remote_heads.trim().len() == 0
+	{
+		let reason: &str = if git_error.trim().len() > 0 { git_error.trim() } else { "not found on the remote" };
+		return Err(reason.to_string());
+	}
+
+	return Ok(());
+}
+
+// Why a branch couldn't be looked up through the REST API: either the branch
+// itself is missing, or the API couldn't be used at all (credentials, network).
+enum ApiLookupError
+{
+	BranchMissing(String),
+	Unreachable(String),
+}
+
+fn lookup_api_branch(tool_context: &ToolContext, branch_name: &String) -> Result<(), ApiLookupError>
+{
+	if tool_context.command_parameters.contains_key("azure")
+	{
+		return match azure_client(tool_context).get_branch_commit_id_blocking(branch_name)
+		{
+			Ok(_) => Ok(()),
+			Err(azure_error @ AzureDevOpsError::BranchNotFound(_)) => Err(ApiLookupError::BranchMissing(azure_error.to_string())),
+			Err(azure_error) => Err(ApiLookupError::Unreachable(azure_error.to_string())),
+		};
+	}
+
+	return match bitbucket_client(tool_context).get_latest_commit_id_blocking(branch_name)
+	{
+		Ok(_) => Ok(()),
+		Err(bitbucket_error @ (BitbucketError::NotFound | BitbucketError::CommitNotFound(_))) => Err(ApiLookupError::BranchMissing(bitbucket_error.to_string())),
+		Err(bitbucket_error) => Err(ApiLookupError::Unreachable(bitbucket_error.to_string())),
+	};
+}
+
+// Prints a PASS/FAIL line per check and leaves a non-zero exit code when any of
+// them failed. Later checks that can't succeed without an earlier one (branches
+// without config, say) are still run, so every problem shows up at once.
+pub fn run_preflight(general_context: &mut Context, tool_context: &mut ToolContext)
+{
+	// Command output would otherwise be interleaved with the checklist
+	let quiet_tool_context: &mut ToolContext = &mut tool_context.clone();
+	quiet_tool_context.printing_on = false;
+
+	let git_mode: bool = quiet_tool_context.command_parameters.contains_key("git");
+	let mut failed_checks: usize = 0;
+
+	print!("Preflight checks:\n");
+	let config_result: Result<(), String> = check_config(quiet_tool_context);
+	let config_complete: bool = config_result.is_ok();
+	report_check(&mut failed_checks, "Configuration is complete", config_result);
+
+	let api_name: &str = if quiet_tool_context.command_parameters.contains_key("azure") { "Azure DevOps" } else { "Bitbucket" };
+	let mut api_unreachable: bool = false;
+
+	if git_mode
+	{
+		report_check(&mut failed_checks, "git is installed", check_git_installed(general_context, quiet_tool_context));
+	}
+	else
+	{
+		// Any request against the repository shows whether the credentials work. A
+		// missing branch is left to the branch checks below.
+		let compare_branch: String = quiet_tool_context.command_parameters.get("branch").cloned().unwrap_or_default();
+		let auth_result: Result<(), String> = if !config_complete
+		{
+			api_unreachable = true;
+			Err(String::from("skipped, the configuration is incomplete"))
+		}
+		else
+		{
+			match lookup_api_branch(quiet_tool_context, &compare_branch)
+			{
+				Ok(()) | Err(ApiLookupError::BranchMissing(_)) => Ok(()),
+				Err(ApiLookupError::Unreachable(reason)) =>
+				{
+					api_unreachable = true;
+					Err(reason)
+				}
+			}
+		};
+
+		report_check(&mut failed_checks, &format!("{} accepts the credentials", api_name), auth_result);
+	}
+
+	let explicit_commits: bool = quiet_tool_context.command_parameters.contains_key("featurecommit");
+
+	if !explicit_commits && !quiet_tool_context.command_parameters.contains_key("feature")
+	{
+		report_check(&mut failed_checks, "Working path is a git repository", check_local_repository(general_context, quiet_tool_context));
+	}
+
+	match branch_names(general_context, quiet_tool_context)
+	{
+		Some(_) if explicit_commits => report_check(&mut failed_checks, "Commits given, no branches to resolve", Ok(())),
+		Some((feature_branch, compare_branch)) =>
+		{
+			for (branch_label, branch_name) in [("Feature", &feature_branch), ("Compare", &compare_branch)]
+			{
+				let check_name: String = format!("{} branch {} resolves", branch_label, branch_name);
+				let branch_result: Result<(), String> = if branch_name.len() == 0
+				{
+					Err(String::from("no branch given, use --feature or run from a checked out branch"))
+				}
+				else if git_mode
+				{
+					check_remote_branch(general_context, quiet_tool_context, branch_name)
+				}
+				else if api_unreachable
+				{
+					Err(format!("skipped, {} could not be reached", api_name))
+				}
+				else
+				{
+					lookup_api_branch(quiet_tool_context, branch_name).map_err(|lookup_error| match lookup_error
+					{
+						ApiLookupError::BranchMissing(reason) | ApiLookupError::Unreachable(reason) => reason,
+					})
+				};
+
+				report_check(&mut failed_checks, &check_name, branch_result);
+			}
+		},
+		None => report_check(&mut failed_checks, "Compare branch is allowed", Err(String::from("not in allowed_compare_branches, run with --force to use it anyway"))),
+	}
+
+	if failed_checks > 0
+	{
+		print!("{} preflight check(s) failed.\n", failed_checks);
+		tool_context.exit_code = 1;
+	}
+	else
+	{
+		print!("All preflight checks passed.\n");
+	}
+}
@@ -0,0 +1,54 @@
+// Reading of the sfdx-project.json file at the root of a Salesforce DX project.
+
+// FILE SYSTEM
+use std::fs as file_system;
+
+use serde_json::Value;
+
+// Returns the path of the packageDirectories entry whose "package" name matches,
+// as written in sfdx-project.json (relative to the project root, without a
+// trailing slash).
+pub fn package_directory_path(repository_path: &String, package_name: &String) -> Result<String, String>
+{
+	let project_file_path: String = format!("{}{}sfdx-project.json", repository_path, crate::slash());
+
+	let project_file_content = file_system::read_to_string(&project_file_path)
+		.map_err(|read_error| format!("unable to read {}: {}", project_file_path, read_error))?;
+
+	let project: Value = serde_json::from_str(&project_file_content)
+		.map_err(|parse_error| format!("unable to parse {}: {}", project_file_path, parse_error))?;
+
+	let package_directories = match project["packageDirectories"].as_array()
+	{
+		Some(package_directories) => package_directories,
+		None => return Err(format!("{} has no packageDirectories", project_file_path)),
+	};
+
+	let mut known_packages: Vec<&str> = Vec::with_capacity(package_directories.len());
+	for package_directory in package_directories
+	{
+		let (Some(directory_package_name), Some(directory_path)) = (package_directory["package"].as_str(), package_directory["path"].as_str())
+		else { continue; };
+
+		if directory_package_name == package_name
+		{
+			let normalized_path: String = directory_path.replace('\\', "/");
+			return Ok(normalized_path.trim_start_matches("./").trim_end_matches('/').to_string());
+		}
+
+		known_packages.push(directory_package_name);
+	}
+
+	return Err(format!("no package named {} in packageDirectories (found: {})", package_name, known_packages.join(", ")));
+}
+
+// Returns the sourceApiVersion from sfdx-project.json, or None when the project
+// file is missing, unreadable or doesn't set one.
+pub fn source_api_version(repository_path: &String) -> Option<String>
+{
+	let project_file_path: String = format!("{}{}sfdx-project.json", repository_path, crate::slash());
+	let project_file_content: String = file_system::read_to_string(&project_file_path).ok()?;
+	let project: Value = serde_json::from_str(&project_file_content).ok()?;
+
+	return project["sourceApiVersion"].as_str().map(|api_version| api_version.trim().to_string());
+}
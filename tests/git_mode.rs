@@ -0,0 +1,520 @@
+// Runs the built executable in Git orchestration mode against a throwaway
+// repository, so the whole pipeline (fetch, diff, parse, write) is exercised
+// without touching anything outside of temporary folders.
+
+// Same explicit return style as the rest of the crate
+#![allow(clippy::needless_return)]
+
+use std::fs as file_system;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use tempfile::TempDir;
+
+fn git(repository_path: &Path, arguments: &[&str])
+{
+	let status = Command::new("git")
+		.args(["-c", "user.name=sfmanifest", "-c", "user.email=sfmanifest@example.com"])
+		.args(arguments)
+		.current_dir(repository_path)
+		.stdout(Stdio::null())
+		.stderr(Stdio::null())
+		.status()
+		.expect("git must be installed to run the integration tests");
+
+	assert!(status.success(), "git {:?} failed", arguments);
+}
+
+fn write_file(repository_path: &Path, relative_path: &str, content: &str)
+{
+	let file_path = repository_path.join(relative_path);
+	file_system::create_dir_all(file_path.parent().unwrap()).unwrap();
+	file_system::write(file_path, content).unwrap();
+}
+
+// A qa branch with an existing class and field, and a feature branch that adds a
+// class and a field and deletes the existing field. File contents differ enough
+// that git doesn't pair the added and deleted fields up as a rename.
+fn create_origin_repository() -> TempDir
+{
+	let origin = tempfile::tempdir().unwrap();
+	let origin_path = origin.path();
+
+	git(origin_path, &["init", "-q"]);
+	git(origin_path, &["checkout", "-q", "-b", "qa"]);
+
+	write_file(origin_path, "force-app/main/default/classes/Existing.cls", "public class Existing {}\n");
+	write_file(origin_path, "force-app/main/default/classes/Existing.cls-meta.xml", "<ApexClass/>\n");
+	write_file(origin_path, "force-app/main/default/objects/Account/fields/Old__c.field-meta.xml", "<CustomField><fullName>Old__c</fullName></CustomField>\n");
+	git(origin_path, &["add", "-A"]);
+	git(origin_path, &["commit", "-q", "-m", "qa"]);
+
+	git(origin_path, &["checkout", "-q", "-b", "feature"]);
+	write_file(origin_path, "force-app/main/default/classes/NewClass.cls", "public class NewClass {}\n");
+	write_file(origin_path, "force-app/main/default/classes/NewClass.cls-meta.xml", "<ApexClass/>\n");
+	write_file(origin_path, "force-app/main/default/objects/Account/fields/New__c.field-meta.xml", "<CustomField><fullName>New__c</fullName></CustomField>\n");
+	file_system::remove_file(origin_path.join("force-app/main/default/objects/Account/fields/Old__c.field-meta.xml")).unwrap();
+	git(origin_path, &["add", "-A"]);
+	git(origin_path, &["commit", "-q", "-m", "feature"]);
+
+	return origin;
+}
+
+fn create_config_directory(origin_path: &Path) -> TempDir
+{
+	let config_directory = tempfile::tempdir().unwrap();
+	let config_content = format!(
+		"bitbucket_username=sfmanifest\nbitbucket_app_password=unused\nbitbucket_workspace=unused\nbitbucket_repository=unused\ngit_remote_url={}\n",
+		origin_path.display()
+	);
+
+	file_system::write(config_directory.path().join("config.txt"), config_content).unwrap();
+	return config_directory;
+}
+
+#[test]
+fn git_mode_writes_manifests_for_the_feature_branch_diff()
+{
+	let origin = create_origin_repository();
+	let config_directory = create_config_directory(origin.path());
+	let working_directory = tempfile::tempdir().unwrap();
+
+	let output = Command::new(env!("CARGO_BIN_EXE_sfmanifest"))
+		.args(["--automation", "git", "--feature", "feature", "--branch", "qa", "--no-color", "--summary-json", "summary.json"])
+		.current_dir(working_directory.path())
+		.env("SFMANIFEST_CONFIG_DIR", config_directory.path())
+		.stdin(Stdio::null())
+		.output()
+		.unwrap();
+
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+
+	let package_xml = file_system::read_to_string(working_directory.path().join("package.xml")).unwrap();
+	assert!(package_xml.contains("<members>NewClass</members>"));
+	assert!(package_xml.contains("<name>ApexClass</name>"));
+	assert!(package_xml.contains("<members>Account.New__c</members>"));
+	assert!(package_xml.contains("<name>CustomField</name>"));
+	assert!(!package_xml.contains("Existing"));
+	assert!(!package_xml.contains("Old__c"));
+
+	let destructive_xml = file_system::read_to_string(working_directory.path().join("destructiveChanges.xml")).unwrap();
+	assert!(destructive_xml.contains("<members>Account.Old__c</members>"));
+	assert!(!destructive_xml.contains("NewClass"));
+
+	let summary: serde_json::Value = serde_json::from_str(&file_system::read_to_string(working_directory.path().join("summary.json")).unwrap()).unwrap();
+	assert_eq!(summary["additive_components"], 2);
+	assert_eq!(summary["destructive_components"], 1);
+	assert_eq!(summary["types"]["CustomField"]["added"], 1);
+	assert_eq!(summary["types"]["CustomField"]["deleted"], 1);
+	assert_eq!(summary["types"]["ApexClass"]["deleted"], 0);
+	assert_eq!(summary["written"], true);
+
+	let leftover_temp_folders: Vec<String> = file_system::read_dir(working_directory.path())
+		.unwrap()
+		.map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+		.filter(|file_name| file_name.contains("_branch_temp"))
+		.collect();
+	assert!(leftover_temp_folders.is_empty(), "temp folders left behind: {:?}", leftover_temp_folders);
+}
+
+#[test]
+fn strict_git_mode_fails_instead_of_skipping_an_unsupported_category()
+{
+	let origin = create_origin_repository();
+	let config_directory = create_config_directory(origin.path());
+	let run_strict = |working_directory: &Path|
+	{
+		return Command::new(env!("CARGO_BIN_EXE_sfmanifest"))
+			.args(["--automation", "git", "--feature", "feature", "--branch", "qa", "--no-color", "--strict"])
+			.current_dir(working_directory)
+			.env("SFMANIFEST_CONFIG_DIR", config_directory.path())
+			.stdin(Stdio::null())
+			.output()
+			.unwrap();
+	};
+
+	let clean_directory = tempfile::tempdir().unwrap();
+	let output = run_strict(clean_directory.path());
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+	assert!(clean_directory.path().join("package.xml").exists());
+
+	write_file(origin.path(), "force-app/main/default/notACategory/Something.thing-meta.xml", "<Thing/>\n");
+	git(origin.path(), &["add", "-A"]);
+	git(origin.path(), &["commit", "-q", "-m", "unsupported"]);
+
+	let strict_directory = tempfile::tempdir().unwrap();
+	let output = run_strict(strict_directory.path());
+	assert_eq!(output.status.code(), Some(1), "{}", String::from_utf8_lossy(&output.stdout));
+	assert!(!strict_directory.path().join("package.xml").exists());
+}
+
+#[test]
+fn max_destructive_fails_when_too_many_members_would_be_deleted()
+{
+	let origin = create_origin_repository();
+	let config_directory = create_config_directory(origin.path());
+	let working_directory = tempfile::tempdir().unwrap();
+	let run_with_limit = |max_destructive: &str|
+	{
+		return Command::new(env!("CARGO_BIN_EXE_sfmanifest"))
+			.args(["--automation", "git", "--feature", "feature", "--branch", "qa", "--no-color", "--max-destructive", max_destructive])
+			.current_dir(working_directory.path())
+			.env("SFMANIFEST_CONFIG_DIR", config_directory.path())
+			.stdin(Stdio::null())
+			.output()
+			.unwrap();
+	};
+
+	let output = run_with_limit("0");
+	assert!(!output.status.success());
+	assert!(String::from_utf8_lossy(&output.stdout).contains("from CustomField (1)"));
+	assert!(!working_directory.path().join("package.xml").exists());
+
+	let output = run_with_limit("1");
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+	assert!(working_directory.path().join("destructiveChanges.xml").exists());
+}
+
+#[test]
+fn git_mode_diffs_explicit_commits_without_branch_names()
+{
+	let origin = create_origin_repository();
+	let config_directory = create_config_directory(origin.path());
+	let working_directory = tempfile::tempdir().unwrap();
+
+	let commit_of = |branch_name: &str|
+	{
+		let output = Command::new("git").args(["rev-parse", branch_name]).current_dir(origin.path()).output().unwrap();
+		return String::from_utf8(output.stdout).unwrap().trim().to_string();
+	};
+
+	let output = Command::new(env!("CARGO_BIN_EXE_sfmanifest"))
+		.args(["--automation", "git", "--no-color"])
+		.args(["--compare-commit", &commit_of("qa"), "--feature-commit", &commit_of("feature")])
+		.current_dir(working_directory.path())
+		.env("SFMANIFEST_CONFIG_DIR", config_directory.path())
+		.stdin(Stdio::null())
+		.output()
+		.unwrap();
+
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+
+	let package_xml = file_system::read_to_string(working_directory.path().join("package.xml")).unwrap();
+	assert!(package_xml.contains("<members>NewClass</members>"));
+	assert!(!package_xml.contains("Existing"));
+}
+
+#[test]
+fn git_mode_fails_when_the_diff_itself_fails()
+{
+	let origin = create_origin_repository();
+	let config_directory = create_config_directory(origin.path());
+	let working_directory = tempfile::tempdir().unwrap();
+
+	let feature_commit = Command::new("git").args(["rev-parse", "feature"]).current_dir(origin.path()).output().unwrap();
+	let feature_commit: String = String::from_utf8(feature_commit.stdout).unwrap().trim().to_string();
+
+	let output = Command::new(env!("CARGO_BIN_EXE_sfmanifest"))
+		.args(["--automation", "git", "--no-color"])
+		.args(["--compare-commit", "0123456789abcdef0123456789abcdef01234567", "--feature-commit", &feature_commit])
+		.current_dir(working_directory.path())
+		.env("SFMANIFEST_CONFIG_DIR", config_directory.path())
+		.stdin(Stdio::null())
+		.output()
+		.unwrap();
+
+	assert!(!output.status.success());
+	assert!(String::from_utf8_lossy(&output.stdout).contains("git diff between"));
+	assert!(!working_directory.path().join("package.xml").exists());
+}
+
+#[test]
+fn patch_file_is_read_without_branches_or_credentials()
+{
+	let working_directory = tempfile::tempdir().unwrap();
+	let config_directory = tempfile::tempdir().unwrap();
+	write_file(working_directory.path(), "bug-report.patch", "\
+diff --git a/force-app/main/default/classes/NewClass.cls b/force-app/main/default/classes/NewClass.cls
+new file mode 100644
+--- /dev/null
++++ b/force-app/main/default/classes/NewClass.cls
+@@ -0,0 +1 @@
++public class NewClass {}
+diff --git a/force-app/main/default/objects/Account/fields/Old__c.field-meta.xml b/force-app/main/default/objects/Account/fields/Old__c.field-meta.xml
+deleted file mode 100644
+");
+
+	let output = Command::new(env!("CARGO_BIN_EXE_sfmanifest"))
+		.args(["--patch-file", "bug-report.patch", "--no-color"])
+		.current_dir(working_directory.path())
+		.env("SFMANIFEST_CONFIG_DIR", config_directory.path())
+		.stdin(Stdio::null())
+		.output()
+		.unwrap();
+
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+
+	let package_xml = file_system::read_to_string(working_directory.path().join("package.xml")).unwrap();
+	assert!(package_xml.contains("<members>NewClass</members>"));
+
+	let destructive_xml = file_system::read_to_string(working_directory.path().join("destructiveChanges.xml")).unwrap();
+	assert!(destructive_xml.contains("<members>Account.Old__c</members>"));
+}
+
+#[test]
+fn list_files_prints_only_the_paths_on_standard_out()
+{
+	let working_directory = tempfile::tempdir().unwrap();
+	let config_directory = tempfile::tempdir().unwrap();
+	write_file(working_directory.path(), "change.diff", "\
+diff --git a/force-app/main/default/classes/B.cls b/force-app/main/default/classes/B.cls
+new file mode 100644
+diff --git a/README.md b/README.md
+index 1111111..2222222 100644
+diff --git a/force-app/main/default/triggers/A.trigger b/force-app/main/default/triggers/A.trigger
+deleted file mode 100644
+");
+
+	let output = Command::new(env!("CARGO_BIN_EXE_sfmanifest"))
+		.args(["--patch-file", "change.diff", "--no-color", "--list-files"])
+		.current_dir(working_directory.path())
+		.env("SFMANIFEST_CONFIG_DIR", config_directory.path())
+		.stdin(Stdio::null())
+		.output()
+		.unwrap();
+
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	assert_eq!(
+		String::from_utf8_lossy(&output.stdout),
+		"force-app/main/default/classes/B.cls\nforce-app/main/default/triggers/A.trigger\n"
+	);
+	assert!(!working_directory.path().join("package.xml").exists());
+}
+
+#[test]
+fn json_log_format_prints_one_record_per_message()
+{
+	let working_directory = tempfile::tempdir().unwrap();
+	let config_directory = tempfile::tempdir().unwrap();
+	write_file(working_directory.path(), "change.diff", "\
+diff --git a/force-app/main/default/classes/NewClass.cls b/force-app/main/default/classes/NewClass.cls
+new file mode 100644
+");
+
+	let output = Command::new(env!("CARGO_BIN_EXE_sfmanifest"))
+		.args(["--patch-file", "change.diff", "--no-color", "--log-format", "json"])
+		.current_dir(working_directory.path())
+		.env("SFMANIFEST_CONFIG_DIR", config_directory.path())
+		.stdin(Stdio::null())
+		.output()
+		.unwrap();
+
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+
+	let log_records: Vec<serde_json::Value> = String::from_utf8_lossy(&output.stdout)
+		.lines()
+		.map(|line| serde_json::from_str(line).unwrap_or_else(|parse_error| panic!("{:?} is not JSON: {}", line, parse_error)))
+		.collect();
+
+	assert!(log_records.iter().any(|record| record["phase"] == "parsing" && record["level"] == "INFO"));
+	assert!(log_records.iter().all(|record| record["timestamp"].is_string() && record["message"].is_string()));
+}
+
+#[test]
+fn incremental_runs_add_new_changes_to_the_baseline()
+{
+	let origin = create_origin_repository();
+	let config_directory = create_config_directory(origin.path());
+	let working_directory = tempfile::tempdir().unwrap();
+	let run_sfmanifest = |extra_arguments: &[&str]|
+	{
+		return Command::new(env!("CARGO_BIN_EXE_sfmanifest"))
+			.args(["--automation", "git", "--feature", "feature", "--branch", "qa", "--no-color"])
+			.args(extra_arguments)
+			.current_dir(working_directory.path())
+			.env("SFMANIFEST_CONFIG_DIR", config_directory.path())
+			.stdin(Stdio::null())
+			.output()
+			.unwrap();
+	};
+
+	let output = run_sfmanifest(&["--incremental"]);
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+	assert!(working_directory.path().join(".sfmanifest-baseline.json").exists());
+
+	write_file(origin.path(), "force-app/main/default/classes/LaterClass.cls", "public class LaterClass {}\n");
+	git(origin.path(), &["add", "-A"]);
+	git(origin.path(), &["commit", "-q", "-m", "later"]);
+
+	let output = run_sfmanifest(&["--incremental"]);
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+	assert!(String::from_utf8_lossy(&output.stdout).contains("Comparing against the incremental baseline"));
+
+	let package_xml = file_system::read_to_string(working_directory.path().join("package.xml")).unwrap();
+	assert!(package_xml.contains("<members>NewClass</members>"));
+	assert!(package_xml.contains("<members>LaterClass</members>"));
+
+	let destructive_xml = file_system::read_to_string(working_directory.path().join("destructiveChanges.xml")).unwrap();
+	assert!(destructive_xml.contains("<members>Account.Old__c</members>"));
+
+	let output = run_sfmanifest(&["--reset-baseline"]);
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+	let baseline_state = file_system::read_to_string(working_directory.path().join(".sfmanifest-baseline.json")).unwrap();
+	assert!(!baseline_state.contains("\"feature\""));
+}
+
+#[test]
+fn preflight_checks_both_branches_on_the_remote()
+{
+	let origin = create_origin_repository();
+	let config_directory = create_config_directory(origin.path());
+	let working_directory = tempfile::tempdir().unwrap();
+	let run_preflight = |feature_branch: &str|
+	{
+		return Command::new(env!("CARGO_BIN_EXE_sfmanifest"))
+			.args(["--automation", "git", "--feature", feature_branch, "--branch", "qa", "--no-color", "--preflight"])
+			.current_dir(working_directory.path())
+			.env("SFMANIFEST_CONFIG_DIR", config_directory.path())
+			.stdin(Stdio::null())
+			.output()
+			.unwrap();
+	};
+
+	let output = run_preflight("feature");
+	let standard_out = String::from_utf8_lossy(&output.stdout);
+	assert!(output.status.success(), "{}", standard_out);
+	assert!(standard_out.contains("[PASS] Feature branch feature resolves"), "{}", standard_out);
+	assert!(standard_out.contains("[PASS] Compare branch qa resolves"), "{}", standard_out);
+
+	let output = run_preflight("missing");
+	let standard_out = String::from_utf8_lossy(&output.stdout);
+	assert_eq!(output.status.code(), Some(1), "{}", standard_out);
+	assert!(standard_out.contains("[FAIL] Feature branch missing resolves"), "{}", standard_out);
+	assert!(!working_directory.path().join("package.xml").exists());
+}
+
+#[test]
+fn since_last_tag_compares_against_the_most_recent_tag()
+{
+	let origin = create_origin_repository();
+	let config_directory = create_config_directory(origin.path());
+	let run_since_last_tag = |working_directory: &Path|
+	{
+		return Command::new(env!("CARGO_BIN_EXE_sfmanifest"))
+			.args(["--automation", "git", "--feature", "feature", "--branch", "qa", "--no-color", "--since-last-tag"])
+			.current_dir(working_directory)
+			.env("SFMANIFEST_CONFIG_DIR", config_directory.path())
+			.stdin(Stdio::null())
+			.output()
+			.unwrap();
+	};
+
+	let untagged_directory = tempfile::tempdir().unwrap();
+	let output = run_since_last_tag(untagged_directory.path());
+	assert_eq!(output.status.code(), Some(1), "{}", String::from_utf8_lossy(&output.stdout));
+
+	git(origin.path(), &["tag", "v1.0", "qa"]);
+
+	let tagged_directory = tempfile::tempdir().unwrap();
+	let output = run_since_last_tag(tagged_directory.path());
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+
+	let package_xml = file_system::read_to_string(tagged_directory.path().join("package.xml")).unwrap();
+	assert!(package_xml.contains("<members>NewClass</members>"));
+	assert!(!package_xml.contains("Existing"));
+}
+
+#[test]
+fn since_rejects_dates_with_shell_characters()
+{
+	let origin = create_origin_repository();
+	let config_directory = create_config_directory(origin.path());
+	let working_directory = tempfile::tempdir().unwrap();
+
+	let output = Command::new(env!("CARGO_BIN_EXE_sfmanifest"))
+		.args(["--automation", "git", "--feature", "feature", "--branch", "qa", "--no-color", "--since", "1 week ago; touch injected"])
+		.current_dir(working_directory.path())
+		.env("SFMANIFEST_CONFIG_DIR", config_directory.path())
+		.stdin(Stdio::null())
+		.output()
+		.unwrap();
+
+	assert!(!output.status.success());
+	assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid date"), "{}", String::from_utf8_lossy(&output.stderr));
+	assert!(!working_directory.path().join("injected").exists());
+	assert!(!working_directory.path().join("package.xml").exists());
+}
+
+#[test]
+fn diff_manifests_fails_when_a_manifest_cannot_be_read()
+{
+	let working_directory = tempfile::tempdir().unwrap();
+	write_file(working_directory.path(), "package.xml", "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Package xmlns=\"http://soap.sforce.com/2006/04/metadata\">\n</Package>\n");
+	write_file(working_directory.path(), "notes.txt", "not a manifest\n");
+
+	let run_diff_manifests = |updated_path: &str|
+	{
+		return Command::new(env!("CARGO_BIN_EXE_sfmanifest"))
+			.args(["--diff-manifests", "package.xml", updated_path])
+			.current_dir(working_directory.path())
+			.stdin(Stdio::null())
+			.output()
+			.unwrap();
+	};
+
+	assert!(run_diff_manifests("package.xml").status.success());
+	assert_eq!(run_diff_manifests("missing.xml").status.code(), Some(1));
+	assert_eq!(run_diff_manifests("notes.txt").status.code(), Some(1));
+}
+
+#[test]
+fn always_include_entries_are_written_without_any_changes()
+{
+	let origin = create_origin_repository();
+	let config_directory = create_config_directory(origin.path());
+	let working_directory = tempfile::tempdir().unwrap();
+
+	let output = Command::new(env!("CARGO_BIN_EXE_sfmanifest"))
+		.args(["--automation", "git", "--feature", "qa", "--branch", "qa", "--no-color", "--always-include", "ApexClass:AlwaysDeployed"])
+		.current_dir(working_directory.path())
+		.env("SFMANIFEST_CONFIG_DIR", config_directory.path())
+		.stdin(Stdio::null())
+		.output()
+		.unwrap();
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+
+	let package_xml = file_system::read_to_string(working_directory.path().join("package.xml")).unwrap();
+	assert!(package_xml.contains("<members>AlwaysDeployed</members>"), "{}", package_xml);
+}
+
+#[test]
+fn destructive_statuses_must_be_status_letters()
+{
+	let working_directory = tempfile::tempdir().unwrap();
+
+	let output = Command::new(env!("CARGO_BIN_EXE_sfmanifest"))
+		.args(["--automation", "git", "--destructive-statuses", "D,M"])
+		.current_dir(working_directory.path())
+		.stdin(Stdio::null())
+		.output()
+		.unwrap();
+
+	assert!(!output.status.success());
+	assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid status M"), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn supported_metadata_leaves_out_object_child_folders()
+{
+	let output = Command::new(env!("CARGO_BIN_EXE_sfmanifest"))
+		.args(["--supported", "--format", "json"])
+		.stdin(Stdio::null())
+		.output()
+		.unwrap();
+	assert!(output.status.success());
+
+	let folder_types: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+	assert_eq!(folder_types["objects"], "CustomObject");
+	assert!(folder_types.get("fields").is_none());
+	assert!(folder_types.get("validationRules").is_none());
+}